@@ -4,6 +4,7 @@
  */
 
 extern crate bindgen;
+extern crate cc;
 extern crate cmake;
 
 use cmake::Config;
@@ -33,6 +34,10 @@ const LLVM_LIBRARIES: &[&str] = &[
     "LLVMipo",
 ];
 
+// always the same, but exported to `wamr_sys` like the feature flags
+const ENABLE_SIMD: &str = "1";
+const ENABLE_MEMORY64: &str = "0";
+
 fn check_is_espidf() -> bool {
     let is_espidf = env::var("CARGO_FEATURE_ESP_IDF").is_ok()
         && env::var("CARGO_CFG_TARGET_OS").unwrap() == "espidf";
@@ -47,42 +52,72 @@ fn check_is_espidf() -> bool {
     is_espidf
 }
 
-fn get_feature_flags() -> (String, String, String, String, String, String) {
-    let enable_custom_section = if cfg!(feature = "custom-section") {
-        "1"
-    } else {
-        "0"
-    };
-    let enable_dump_call_stack = if cfg!(feature = "dump-call-stack") {
-        "1"
-    } else {
-        "0"
-    };
-    let enable_llvm_jit = if cfg!(feature = "llvmjit") { "1" } else { "0" };
-    let enable_multi_module = if cfg!(feature = "multi-module") {
-        "1"
-    } else {
-        "0"
-    };
-    let enable_name_section = if cfg!(feature = "name-section") {
-        "1"
-    } else {
-        "0"
-    };
-    let disable_hw_bound_check = if cfg!(feature = "hw-bound-check") {
-        "0"
-    } else {
-        "1"
-    };
-
-    (
-        enable_custom_section.to_string(),
-        enable_dump_call_stack.to_string(),
-        enable_llvm_jit.to_string(),
-        enable_multi_module.to_string(),
-        enable_name_section.to_string(),
-        disable_hw_bound_check.to_string(),
-    )
+struct FeatureFlags {
+    enable_custom_section: String,
+    enable_debug_interp: String,
+    enable_fast_interp: String,
+    enable_fast_jit: String,
+    enable_gc: String,
+    enable_dump_call_stack: String,
+    enable_linux_perf: String,
+    enable_llvm_jit: String,
+    enable_log_hook: String,
+    enable_memory_profiling: String,
+    enable_multi_module: String,
+    enable_name_section: String,
+    enable_perf_profiling: String,
+    disable_hw_bound_check: String,
+    enable_instruction_metering: String,
+    enable_wasi_threads: String,
+}
+
+fn to_flag(enabled: bool) -> String {
+    String::from(if enabled { "1" } else { "0" })
+}
+
+fn get_feature_flags() -> FeatureFlags {
+    FeatureFlags {
+        enable_custom_section: to_flag(cfg!(feature = "custom-section")),
+        enable_debug_interp: to_flag(cfg!(feature = "debug-interp")),
+        // the classic interpreter reports instruction offsets in the module binary.
+        // the debug engine works only with it
+        enable_fast_interp: to_flag(!cfg!(feature = "classic-interp")),
+        enable_fast_jit: to_flag(cfg!(feature = "fast-jit")),
+        enable_gc: to_flag(cfg!(feature = "gc")),
+        enable_dump_call_stack: to_flag(cfg!(feature = "dump-call-stack")),
+        enable_linux_perf: to_flag(cfg!(feature = "linux-perf")),
+        enable_llvm_jit: to_flag(cfg!(feature = "llvmjit")),
+        enable_log_hook: to_flag(cfg!(feature = "log-hook")),
+        enable_memory_profiling: to_flag(cfg!(feature = "memory-profiling")),
+        enable_multi_module: to_flag(cfg!(feature = "multi-module")),
+        enable_name_section: to_flag(cfg!(feature = "name-section")),
+        enable_perf_profiling: to_flag(cfg!(feature = "perf-profiling")),
+        disable_hw_bound_check: to_flag(!cfg!(feature = "hw-bound-check")),
+        enable_instruction_metering: to_flag(cfg!(feature = "instruction-metering")),
+        enable_wasi_threads: to_flag(cfg!(feature = "wasi-threads")),
+    }
+}
+
+/// the build configuration for the constants of `wamr_sys`, like `WAMR_BUILD_SIMD`
+fn export_build_config(feature_flags: &FeatureFlags) {
+    let build_config = [
+        ("FAST_INTERP", feature_flags.enable_fast_interp.as_str()),
+        ("FAST_JIT", &feature_flags.enable_fast_jit),
+        ("JIT", &feature_flags.enable_llvm_jit),
+        ("SIMD", ENABLE_SIMD),
+        // wasi-threads needs shared memories
+        ("SHARED_MEMORY", &feature_flags.enable_wasi_threads),
+        ("LIB_WASI_THREADS", &feature_flags.enable_wasi_threads),
+        ("MEMORY64", ENABLE_MEMORY64),
+        ("GC", &feature_flags.enable_gc),
+        ("DEBUG_INTERP", &feature_flags.enable_debug_interp),
+        ("PERF_PROFILING", &feature_flags.enable_perf_profiling),
+        ("MEMORY_PROFILING", &feature_flags.enable_memory_profiling),
+    ];
+
+    for (name, flag) in build_config {
+        println!("cargo:rustc-env=WAMR_SYS_BUILD_{}={}", name, flag);
+    }
 }
 
 fn link_llvm_libraries(llvm_cfg_path: &String, enable_llvm_jit: &String) {
@@ -109,34 +144,59 @@ fn link_llvm_libraries(llvm_cfg_path: &String, enable_llvm_jit: &String) {
     }
 }
 
-fn setup_config(
-    wamr_root: &PathBuf,
-    feature_flags: (String, String, String, String, String, String),
-) -> Config {
-    let (
+fn setup_config(wamr_root: &PathBuf, feature_flags: FeatureFlags) -> Config {
+    let FeatureFlags {
         enable_custom_section,
+        enable_debug_interp,
+        enable_fast_interp,
+        enable_fast_jit,
+        enable_gc,
         enable_dump_call_stack,
+        enable_linux_perf,
         enable_llvm_jit,
+        enable_log_hook,
+        enable_memory_profiling,
         enable_multi_module,
         enable_name_section,
-        disalbe_hw_bound_check,
-    ) = feature_flags;
+        enable_perf_profiling,
+        disable_hw_bound_check,
+        enable_instruction_metering,
+        enable_wasi_threads,
+    } = feature_flags;
 
     let mut cfg = Config::new(wamr_root);
     cfg.define("WAMR_BUILD_AOT", "1")
         .define("WAMR_BUILD_INTERP", "1")
-        .define("WAMR_BUILD_FAST_INTERP", "1")
+        .define("WAMR_BUILD_FAST_INTERP", &enable_fast_interp)
         .define("WAMR_BUILD_JIT", &enable_llvm_jit)
+        // with LLVM JIT too, it is multi-tier JIT
+        .define("WAMR_BUILD_FAST_JIT", &enable_fast_jit)
         .define("WAMR_BUILD_BULK_MEMORY", "1")
         .define("WAMR_BUILD_REF_TYPES", "1")
-        .define("WAMR_BUILD_SIMD", "1")
+        .define("WAMR_BUILD_SIMD", ENABLE_SIMD)
+        .define("WAMR_BUILD_SHARED_MEMORY", &enable_wasi_threads)
+        .define("WAMR_BUILD_LIB_WASI_THREADS", &enable_wasi_threads)
+        .define("WAMR_BUILD_MEMORY64", ENABLE_MEMORY64)
+        .define("WAMR_BUILD_GC", &enable_gc)
         .define("WAMR_BUILD_LIBC_WASI", "1")
         .define("WAMR_BUILD_LIBC_BUILTIN", "0")
-        .define("WAMR_DISABLE_HW_BOUND_CHECK", &disalbe_hw_bound_check)
+        // wasm_runtime_terminate() relies on the suspend flags of the thread manager
+        // to stop a running instance from another thread
+        .define("WAMR_BUILD_THREAD_MGR", "1")
+        .define("WAMR_DISABLE_HW_BOUND_CHECK", &disable_hw_bound_check)
         .define("WAMR_BUILD_MULTI_MODULE", &enable_multi_module)
         .define("WAMR_BUILD_DUMP_CALL_STACK", &enable_dump_call_stack)
         .define("WAMR_BUILD_CUSTOM_NAME_SECTION", &enable_name_section)
-        .define("WAMR_BUILD_LOAD_CUSTOM_SECTION", &enable_custom_section);
+        .define("WAMR_BUILD_LOAD_CUSTOM_SECTION", &enable_custom_section)
+        .define("WAMR_BUILD_DEBUG_INTERP", &enable_debug_interp)
+        .define("WAMR_BUILD_PERF_PROFILING", &enable_perf_profiling)
+        .define("WAMR_BUILD_MEMORY_PROFILING", &enable_memory_profiling)
+        // perf map of JIT and AOT functions, /tmp/perf-<pid>.map
+        .define("WAMR_BUILD_LINUX_PERF", &enable_linux_perf)
+        .define(
+            "WAMR_BUILD_INSTRUCTION_METERING",
+            &enable_instruction_metering,
+        );
 
     // always assume non-empty strings for these environment variables
 
@@ -157,14 +217,35 @@ fn setup_config(
         cfg.define("LLVM_DIR", &llvm_cfg_path);
     }
 
-    // STDIN/STDOUT/STDERR redirect
+    // STDIN/STDOUT/STDERR redirect. a custom callback wins over the log hook
     if let Ok(bh_vprintf) = env::var("WAMR_BH_VPRINTF") {
         cfg.define("WAMR_BH_VPRINTF", &bh_vprintf);
+    } else if enable_log_hook == "1" {
+        cfg.define("WAMR_BH_VPRINTF", "wamr_sys_vprintf");
+    }
+
+    // replaces bh_log(), which gets the level of every log. the same as above
+    if let Ok(bh_log) = env::var("WAMR_BH_LOG") {
+        cfg.define("WAMR_BH_LOG", &bh_log);
+    } else if enable_log_hook == "1" {
+        cfg.define("WAMR_BH_LOG", "wamr_sys_bh_log");
     }
 
     cfg
 }
 
+/// the C++ standard library of the target. MSVC links its own
+fn cxx_runtime() -> Option<&'static str> {
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+
+    match (target_os.as_str(), target_env.as_str()) {
+        (_, "msvc") => None,
+        ("macos" | "ios" | "tvos" | "watchos" | "freebsd" | "openbsd", _) => Some("c++"),
+        _ => Some("stdc++"),
+    }
+}
+
 fn build_wamr_libraries(wamr_root: &PathBuf) {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let vmbuild_path = out_dir.join("vmbuild");
@@ -175,6 +256,22 @@ fn build_wamr_libraries(wamr_root: &PathBuf) {
 
     println!("cargo:rustc-link-search=native={}/build", dst.display());
     println!("cargo:rustc-link-lib=static=iwasm");
+
+    // Fast JIT is built on asmjit, a C++ library
+    if cfg!(feature = "fast-jit") {
+        if let Some(cxx_runtime) = cxx_runtime() {
+            println!("cargo:rustc-link-lib=dylib={}", cxx_runtime);
+        }
+    }
+}
+
+// after vmlib, which refers to `wamr_sys_vprintf()` and `wamr_sys_bh_log()`
+fn build_log_hook() {
+    println!("cargo:rerun-if-changed=src/log_hook.c");
+
+    cc::Build::new()
+        .file("src/log_hook.c")
+        .compile("wamrsyslog");
 }
 
 fn build_wamrc(wamr_root: &Path) {
@@ -184,11 +281,21 @@ fn build_wamrc(wamr_root: &Path) {
     let wamr_compiler_path = wamr_root.join("wamr-compiler");
     assert!(wamr_compiler_path.exists());
 
-    Config::new(&wamr_compiler_path)
+    let dst = Config::new(&wamr_compiler_path)
         .out_dir(wamrc_build_path)
         .define("WAMR_BUILD_WITH_CUSTOM_LLVM", "1")
-        .define("LLVM_DIR", env::var("LLVM_LIB_CFG_PATH").expect("LLVM_LIB_CFG_PATH isn't specified in config.toml"))
+        .define(
+            "LLVM_DIR",
+            env::var("LLVM_LIB_CFG_PATH")
+                .expect("LLVM_LIB_CFG_PATH isn't specified in config.toml"),
+        )
         .build();
+
+    // for `wamr_sys::WAMRC_PATH`
+    println!(
+        "cargo:rustc-env=WAMR_SYS_WAMRC_PATH={}",
+        dst.join("bin").join("wamrc").display()
+    );
 }
 
 fn generate_bindings(wamr_root: &Path) {
@@ -215,16 +322,25 @@ fn main() {
     println!("cargo:rerun-if-env-changed=WAMR_SHARED_PLATFORM_CONFIG");
     println!("cargo:rerun-if-env-changed=LLVM_LIB_CFG_PATH");
     println!("cargo:rerun-if-env-changed=WAMR_BH_VPRINTF");
+    println!("cargo:rerun-if-env-changed=WAMR_BH_LOG");
 
     let wamr_root = env::current_dir().unwrap();
     let wamr_root = wamr_root.join("wasm-micro-runtime");
     assert!(wamr_root.exists());
 
+    export_build_config(&get_feature_flags());
+
     if !check_is_espidf() {
         // because the ESP-IDF build procedure differs from the regular one
         // (build internally by esp-idf-sys),
         build_wamr_libraries(&wamr_root);
-        build_wamrc(&wamr_root);
+        if cfg!(feature = "log-hook") {
+            build_log_hook();
+        }
+        // the AOT compiler needs LLVM, see LLVM_LIB_CFG_PATH
+        if cfg!(feature = "wamrc") {
+            build_wamrc(&wamr_root);
+        }
     }
 
     generate_bindings(&wamr_root);
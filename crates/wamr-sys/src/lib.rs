@@ -11,3 +11,71 @@
 
 // This matches bindgen::Builder output
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+/// receives every piece of text the runtime prints, not NUL terminated
+#[cfg(feature = "log-hook")]
+pub type wamr_sys_log_writer_t =
+    Option<unsafe extern "C" fn(message: *const ::core::ffi::c_char, len: ::core::ffi::c_int)>;
+
+/// receives every log of the runtime with its level, one of `log_level_t`, and
+/// where it is logged. the message is neither NUL nor newline terminated
+#[cfg(feature = "log-hook")]
+pub type wamr_sys_log_record_writer_t = Option<
+    unsafe extern "C" fn(
+        level: u32,
+        file: *const ::core::ffi::c_char,
+        line: ::core::ffi::c_int,
+        message: *const ::core::ffi::c_char,
+        len: ::core::ffi::c_int,
+    ),
+>;
+
+#[cfg(feature = "log-hook")]
+extern "C" {
+    /// set the writer of the built-in vprintf callback, src/log_hook.c.
+    /// `None` prints to stdout
+    pub fn wamr_sys_set_log_writer(writer: wamr_sys_log_writer_t);
+
+    /// set the writer of the built-in log callback, src/log_hook.c. unlike the vprintf
+    /// callback, it isn't filtered by `wasm_runtime_set_log_level()`. `None` prints to stdout
+    pub fn wamr_sys_set_log_record_writer(writer: wamr_sys_log_record_writer_t);
+}
+
+#[cfg(feature = "memory-profiling")]
+extern "C" {
+    /// print the memory consumption of a module.
+    /// not in wasm_export.h, but exported by the runtime with WAMR_BUILD_MEMORY_PROFILING
+    pub fn wasm_runtime_dump_module_mem_consumption(module: wasm_module_t);
+}
+
+const fn is_set(flag: &str) -> bool {
+    matches!(flag.as_bytes(), b"1")
+}
+
+// the build configuration of the runtime, as build.rs passes it to cmake.
+// ESP-IDF builds the runtime by its own component instead
+
+/// whether the interpreter is the fast one.
+/// it reports offsets in its own rewritten code instead of the module binary
+pub const WAMR_BUILD_FAST_INTERP: bool = is_set(env!("WAMR_SYS_BUILD_FAST_INTERP"));
+/// Fast JIT
+pub const WAMR_BUILD_FAST_JIT: bool = is_set(env!("WAMR_SYS_BUILD_FAST_JIT"));
+/// LLVM JIT. with Fast JIT, it is multi-tier JIT
+pub const WAMR_BUILD_JIT: bool = is_set(env!("WAMR_SYS_BUILD_JIT"));
+/// v128 in AOT modules and LLVM JIT
+pub const WAMR_BUILD_SIMD: bool = is_set(env!("WAMR_SYS_BUILD_SIMD"));
+/// shared memories and atomics
+pub const WAMR_BUILD_SHARED_MEMORY: bool = is_set(env!("WAMR_SYS_BUILD_SHARED_MEMORY"));
+/// threads spawned by wasm through wasi-threads
+pub const WAMR_BUILD_LIB_WASI_THREADS: bool = is_set(env!("WAMR_SYS_BUILD_LIB_WASI_THREADS"));
+pub const WAMR_BUILD_MEMORY64: bool = is_set(env!("WAMR_SYS_BUILD_MEMORY64"));
+/// garbage collected objects of the GC proposal
+pub const WAMR_BUILD_GC: bool = is_set(env!("WAMR_SYS_BUILD_GC"));
+/// the debug engine, for the debug server
+pub const WAMR_BUILD_DEBUG_INTERP: bool = is_set(env!("WAMR_SYS_BUILD_DEBUG_INTERP"));
+pub const WAMR_BUILD_PERF_PROFILING: bool = is_set(env!("WAMR_SYS_BUILD_PERF_PROFILING"));
+pub const WAMR_BUILD_MEMORY_PROFILING: bool = is_set(env!("WAMR_SYS_BUILD_MEMORY_PROFILING"));
+
+/// the AOT compiler built with the runtime
+#[cfg(feature = "wamrc")]
+pub const WAMRC_PATH: &str = env!("WAMR_SYS_WAMRC_PATH");
@@ -0,0 +1,116 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+/*
+ * The vprintf callback (WAMR_BH_VPRINTF) and the log callback (WAMR_BH_LOG) of WAMR
+ * with the `log-hook` feature. They format what the runtime prints and pass it to
+ * writers set by Rust, since Rust can't take a va_list. Without a writer, they print
+ * to stdout.
+ */
+
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+typedef void (*wamr_sys_log_writer_t)(const char *message, int len);
+typedef void (*wamr_sys_log_record_writer_t)(uint32_t level, const char *file,
+                                             int line, const char *message,
+                                             int len);
+
+static wamr_sys_log_writer_t log_writer = NULL;
+static wamr_sys_log_record_writer_t log_record_writer = NULL;
+
+void
+wamr_sys_set_log_writer(wamr_sys_log_writer_t writer)
+{
+    log_writer = writer;
+}
+
+void
+wamr_sys_set_log_record_writer(wamr_sys_log_record_writer_t writer)
+{
+    log_record_writer = writer;
+}
+
+/* the length of the message in *p_message, which has to be freed if not buf */
+static int
+format_message(char *buf, size_t size, char **p_message, const char *format,
+               va_list ap)
+{
+    char *message = buf;
+    va_list ap_copy;
+    int len;
+
+    va_copy(ap_copy, ap);
+    len = vsnprintf(buf, size, format, ap);
+    if (len >= (int)size) {
+        message = malloc((size_t)len + 1);
+        if (message) {
+            vsnprintf(message, (size_t)len + 1, format, ap_copy);
+        }
+        else {
+            /* keep what fits */
+            message = buf;
+            len = (int)size - 1;
+        }
+    }
+    va_end(ap_copy);
+
+    *p_message = message;
+    return len;
+}
+
+int
+wamr_sys_vprintf(const char *format, va_list ap)
+{
+    char buf[512];
+    char *message;
+    int len;
+
+    if (!log_writer) {
+        return vprintf(format, ap);
+    }
+
+    len = format_message(buf, sizeof(buf), &message, format, ap);
+    if (len > 0) {
+        log_writer(message, len);
+    }
+
+    if (message != buf) {
+        free(message);
+    }
+    return len;
+}
+
+/* replaces bh_log(), so the level is the one of the LOG_xxx macro */
+void
+wamr_sys_bh_log(uint32_t level, const char *file, int line, const char *format,
+                ...)
+{
+    char buf[512];
+    char *message;
+    va_list ap;
+    int len;
+
+    va_start(ap, format);
+    if (!log_record_writer) {
+        vprintf(format, ap);
+        printf("\n");
+        va_end(ap);
+        return;
+    }
+
+    len = format_message(buf, sizeof(buf), &message, format, ap);
+    va_end(ap);
+
+    if (len >= 0) {
+        log_record_writer(level, file ? file : "", line, message, len);
+    }
+
+    if (message != buf) {
+        free(message);
+    }
+}
@@ -0,0 +1,163 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! forward what the runtime allocates for itself to a Rust `GlobalAlloc`,
+//! see `RuntimeBuilder::use_allocator()`
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    ffi::{c_uint, c_void},
+    ptr,
+    sync::RwLock,
+};
+
+// keep the size before every block, which C doesn't pass to free()
+const HEADER_SIZE: usize = 16;
+// as malloc() of C
+const ALIGN: usize = 16;
+
+// the allocator of the live runtime. there is at most one runtime per process
+static ALLOCATOR: RwLock<Option<&'static (dyn GlobalAlloc + Sync)>> = RwLock::new(None);
+
+pub(crate) fn set_allocator(allocator: &'static (dyn GlobalAlloc + Sync)) {
+    *ALLOCATOR
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(allocator);
+}
+
+// the lock only guards a reference, a panic can't leave it half written. and the
+// callbacks below are called from C, where they must not panic
+fn current() -> Option<&'static (dyn GlobalAlloc + Sync)> {
+    *ALLOCATOR
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn layout_of(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER_SIZE)?, ALIGN).ok()
+}
+
+/// the block with the header and the layout of it
+unsafe fn block_of(ptr: *mut c_void) -> (*mut u8, Layout) {
+    let base = (ptr as *mut u8).sub(HEADER_SIZE);
+    let size = (base as *const usize).read();
+    (base, layout_of(size).unwrap())
+}
+
+unsafe fn into_user_ptr(base: *mut u8, size: usize) -> *mut c_void {
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    (base as *mut usize).write(size);
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+pub(crate) extern "C" fn malloc(size: c_uint) -> *mut c_void {
+    match current() {
+        Some(allocator) => malloc_with(allocator, size),
+        None => ptr::null_mut(),
+    }
+}
+
+pub(crate) extern "C" fn realloc(ptr: *mut c_void, size: c_uint) -> *mut c_void {
+    match current() {
+        Some(allocator) => realloc_with(allocator, ptr, size),
+        None => ptr::null_mut(),
+    }
+}
+
+pub(crate) extern "C" fn free(ptr: *mut c_void) {
+    if let Some(allocator) = current() {
+        free_with(allocator, ptr);
+    }
+}
+
+fn malloc_with(allocator: &dyn GlobalAlloc, size: c_uint) -> *mut c_void {
+    let size = size as usize;
+    match layout_of(size) {
+        Some(layout) => unsafe { into_user_ptr(allocator.alloc(layout), size) },
+        None => ptr::null_mut(),
+    }
+}
+
+fn realloc_with(allocator: &dyn GlobalAlloc, ptr: *mut c_void, size: c_uint) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc_with(allocator, size);
+    }
+
+    let size = size as usize;
+    if layout_of(size).is_none() {
+        return ptr::null_mut();
+    }
+    unsafe {
+        let (base, layout) = block_of(ptr);
+        let base = allocator.realloc(base, layout, size + HEADER_SIZE);
+        into_user_ptr(base, size)
+    }
+}
+
+fn free_with(allocator: &dyn GlobalAlloc, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    unsafe {
+        let (base, layout) = block_of(ptr);
+        allocator.dealloc(base, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        alloc::System,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct Counter {
+        allocated: AtomicUsize,
+    }
+
+    unsafe impl GlobalAlloc for Counter {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.allocated.fetch_add(layout.size(), Ordering::SeqCst);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.allocated.fetch_sub(layout.size(), Ordering::SeqCst);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    // a local allocator, the global one belongs to the runtime of other tests
+    #[test]
+    fn test_allocator_forwarding() {
+        let counter = Counter {
+            allocated: AtomicUsize::new(0),
+        };
+
+        let block = malloc_with(&counter, 100);
+        assert!(!block.is_null());
+        assert_eq!(block as usize % ALIGN, 0);
+        assert_eq!(counter.allocated.load(Ordering::SeqCst), 100 + HEADER_SIZE);
+
+        unsafe { ptr::write_bytes(block as *mut u8, 0xab, 100) };
+        let block = realloc_with(&counter, block, 200);
+        assert!(!block.is_null());
+        assert_eq!(unsafe { *(block as *const u8).add(99) }, 0xab);
+        assert_eq!(counter.allocated.load(Ordering::SeqCst), 200 + HEADER_SIZE);
+
+        free_with(&counter, block);
+        assert_eq!(counter.allocated.load(Ordering::SeqCst), 0);
+
+        let block = realloc_with(&counter, ptr::null_mut(), 8);
+        assert!(!block.is_null());
+        free_with(&counter, block);
+        free_with(&counter, ptr::null_mut());
+        assert_eq!(counter.allocated.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,454 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! compile .wasm into .aot ahead of time, with the `wamrc` feature.
+//! it runs the `wamrc` built with wamr-sys, or another one given to `Compiler::with_path()`.
+//! the output can be passed to `Module::from_vec()`
+
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    env,
+    fs::{self, DirBuilder},
+    hash::{BuildHasher, Hasher},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use wamr_sys::WAMRC_PATH;
+
+use crate::RuntimeError;
+
+/// the highest `opt_level` and `size_level`
+const MAX_LEVEL: u32 = 3;
+
+/// A WebAssembly proposal `wamrc` can compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Proposal {
+    /// enabled by default
+    BulkMemory,
+    /// enabled by default
+    ReferenceTypes,
+    /// shared memories and atomics
+    Threads,
+    TailCall,
+    ExtendedConst,
+    Gc,
+    Memory64,
+}
+
+impl Proposal {
+    fn is_default(self) -> bool {
+        matches!(self, Proposal::BulkMemory | Proposal::ReferenceTypes)
+    }
+
+    /// the `wamrc` option to turn the proposal on or off, if it isn't already
+    fn option(self, enabled: bool) -> Option<&'static str> {
+        if enabled == self.is_default() {
+            return None;
+        }
+
+        Some(match self {
+            Proposal::BulkMemory => "--disable-bulk-memory",
+            Proposal::ReferenceTypes => "--disable-ref-types",
+            Proposal::Threads => "--enable-multi-thread",
+            Proposal::TailCall => "--enable-tail-call",
+            Proposal::ExtendedConst => "--enable-extended-const",
+            Proposal::Gc => "--enable-gc",
+            Proposal::Memory64 => "--enable-memory64",
+        })
+    }
+}
+
+/// The AOT compiler. Options not set are the defaults of `wamrc`, which
+/// generates code for the host.
+///
+/// ```ignore
+/// let aot = Compiler::new().opt_level(3)?.bounds_checks(true).compile(&wasm)?;
+/// let module = Module::from_vec(&runtime, aot, "plugin")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    wamrc: PathBuf,
+    target: Option<String>,
+    target_abi: Option<String>,
+    cpu: Option<String>,
+    cpu_features: Option<String>,
+    opt_level: Option<u32>,
+    size_level: Option<u32>,
+    bounds_checks: Option<bool>,
+    simd: bool,
+    xip: bool,
+    proposals: BTreeMap<Proposal, bool>,
+    multi_module: bool,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler {
+            wamrc: PathBuf::from(WAMRC_PATH),
+            target: None,
+            target_abi: None,
+            cpu: None,
+            cpu_features: None,
+            opt_level: None,
+            size_level: None,
+            bounds_checks: None,
+            simd: true,
+            xip: false,
+            proposals: BTreeMap::new(),
+            multi_module: false,
+        }
+    }
+}
+
+impl Compiler {
+    /// run the `wamrc` built with wamr-sys, `wamr_sys::WAMRC_PATH`
+    pub fn new() -> Self {
+        Compiler::default()
+    }
+
+    /// run another `wamrc`, like one of a relocated target directory
+    pub fn with_path<P: AsRef<Path>>(wamrc: P) -> Self {
+        Compiler {
+            wamrc: wamrc.as_ref().to_path_buf(),
+            ..Compiler::default()
+        }
+    }
+
+    /// the target architecture, like `x86_64`, `aarch64v8`, `thumbv7em` or `riscv64`
+    pub fn target(mut self, arch: &str) -> Compiler {
+        self.target = Some(String::from(arch));
+        self
+    }
+
+    /// the target ABI, like `gnu`, `eabi`, `gnueabihf` or `msvc`
+    pub fn target_abi(mut self, abi: &str) -> Compiler {
+        self.target_abi = Some(String::from(abi));
+        self
+    }
+
+    /// the target CPU, like `skylake` or `cortex-m4`
+    pub fn cpu(mut self, cpu: &str) -> Compiler {
+        self.cpu = Some(String::from(cpu));
+        self
+    }
+
+    /// the CPU features in the LLVM form, like `+sse4.2,-avx`
+    pub fn cpu_features(mut self, features: &str) -> Compiler {
+        self.cpu_features = Some(String::from(features));
+        self
+    }
+
+    /// the optimization level, 0 to 3. `wamrc` uses 3 by default
+    ///
+    /// # Errors
+    ///
+    /// if the level is out of range, it will return `RuntimeError::InvalidConfiguration`
+    pub fn opt_level(mut self, level: u32) -> Result<Compiler, RuntimeError> {
+        self.opt_level = Some(check_level("opt level", level)?);
+        Ok(self)
+    }
+
+    /// the code size level, 0 to 3. `wamrc` uses 3 by default
+    ///
+    /// # Errors
+    ///
+    /// if the level is out of range, it will return `RuntimeError::InvalidConfiguration`
+    pub fn size_level(mut self, level: u32) -> Result<Compiler, RuntimeError> {
+        self.size_level = Some(check_level("size level", level)?);
+        Ok(self)
+    }
+
+    /// check linear memory accesses in the generated code. by default, `wamrc` checks
+    /// them unless the target runtime relies on hardware bound checks
+    pub fn bounds_checks(mut self, enable: bool) -> Compiler {
+        self.bounds_checks = Some(enable);
+        self
+    }
+
+    /// compile v128 instructions. it is on by default
+    pub fn simd(mut self, enable: bool) -> Compiler {
+        self.simd = enable;
+        self
+    }
+
+    /// generate execute-in-place code, which runs from read-only memory like flash
+    pub fn xip(mut self, enable: bool) -> Compiler {
+        self.xip = enable;
+        self
+    }
+
+    /// turn a proposal on or off
+    pub fn proposal(mut self, proposal: Proposal, enable: bool) -> Compiler {
+        self.proposals.insert(proposal, enable);
+        self
+    }
+
+    /// compile a module importing other modules. a runtime with the `multi-module`
+    /// feature loads the imported modules when it loads the output
+    pub fn multi_module(mut self, enable: bool) -> Compiler {
+        self.multi_module = enable;
+        self
+    }
+
+    /// the arguments of `wamrc`, except the output and the input
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        let options = [
+            ("--target", &self.target),
+            ("--target-abi", &self.target_abi),
+            ("--cpu", &self.cpu),
+            ("--cpu-features", &self.cpu_features),
+        ];
+        for (name, value) in options {
+            if let Some(value) = value {
+                args.push(format!("{}={}", name, value));
+            }
+        }
+
+        if let Some(level) = self.opt_level {
+            args.push(format!("--opt-level={}", level));
+        }
+        if let Some(level) = self.size_level {
+            args.push(format!("--size-level={}", level));
+        }
+        if let Some(enable) = self.bounds_checks {
+            args.push(format!("--bounds-checks={}", enable as u32));
+        }
+        if !self.simd {
+            args.push(String::from("--disable-simd"));
+        }
+        if self.xip {
+            args.push(String::from("--xip"));
+        }
+        for (proposal, enable) in &self.proposals {
+            if let Some(option) = proposal.option(*enable) {
+                args.push(String::from(option));
+            }
+        }
+        if self.multi_module {
+            args.push(String::from("--enable-multi-module"));
+        }
+        args
+    }
+
+    /// compile a .wasm binary into .aot
+    ///
+    /// # Errors
+    ///
+    /// if `wamrc` fails, it will return `RuntimeError::CompilationError` with its output,
+    /// or with why it can't run. if the temporary files can't be written or read, it will return
+    /// `RuntimeError::WasmFileFSError`
+    pub fn compile(&self, wasm: &[u8]) -> Result<Vec<u8>, RuntimeError> {
+        let dir = TempDir::new()?;
+        let input = dir.path().join("module.wasm");
+        fs::write(&input, wasm).map_err(RuntimeError::WasmFileFSError)?;
+        self.compile_to(&input, &dir.path().join("module.aot"))
+    }
+
+    /// compile a .wasm file into .aot. with `multi_module()`, the imported modules
+    /// are looked up next to it
+    ///
+    /// # Errors
+    ///
+    /// the same as `compile()`
+    pub fn compile_file(&self, wasm_file: &Path) -> Result<Vec<u8>, RuntimeError> {
+        let dir = TempDir::new()?;
+        self.compile_to(wasm_file, &dir.path().join("module.aot"))
+    }
+
+    fn compile_to(&self, input: &Path, output: &Path) -> Result<Vec<u8>, RuntimeError> {
+        let result = Command::new(&self.wamrc)
+            .args(self.args())
+            .arg("-o")
+            .arg(output)
+            .arg(input)
+            .output()
+            .map_err(|e| {
+                RuntimeError::CompilationError(format!("can't run {}: {}", self.wamrc.display(), e))
+            })?;
+
+        // wamrc prints errors to stdout
+        if !result.status.success() || !output.exists() {
+            let mut message = String::from_utf8_lossy(&result.stdout).into_owned();
+            message.push_str(&String::from_utf8_lossy(&result.stderr));
+            return Err(RuntimeError::CompilationError(message.trim().to_string()));
+        }
+
+        fs::read(output).map_err(RuntimeError::WasmFileFSError)
+    }
+}
+
+fn check_level(name: &str, level: u32) -> Result<u32, RuntimeError> {
+    match level {
+        0..=MAX_LEVEL => Ok(level),
+        _ => Err(RuntimeError::InvalidConfiguration(format!(
+            "{} {} is out of 0..={}",
+            name, level, MAX_LEVEL
+        ))),
+    }
+}
+
+/// a directory for the files of one compilation, removed when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    /// how many names to try before giving up
+    const ATTEMPTS: u32 = 16;
+
+    /// a new directory of an unpredictable name. it is never one which already
+    /// exists, which someone else sharing the temporary directory could create
+    fn new() -> Result<TempDir, RuntimeError> {
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        let mut attempt = 0;
+        loop {
+            let path = env::temp_dir().join(format!("wamr-aot-{:016x}", random_suffix()));
+            match builder.create(&path) {
+                Ok(()) => return Ok(TempDir(path)),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && attempt < Self::ATTEMPTS => {
+                    attempt += 1
+                }
+                Err(e) => return Err(RuntimeError::WasmFileFSError(e)),
+            }
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// the randomly seeded hasher of std, over what differs between calls
+fn random_suffix() -> u64 {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(process::id());
+    hasher.write_usize(COUNT.fetch_add(1, Ordering::SeqCst));
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        function::Function, instance::Instance, module::Module, runtime::Runtime, value::WasmValue,
+    };
+
+    #[test]
+    fn test_compiler_args() {
+        assert!(Compiler::new().args().is_empty());
+
+        let compiler = Compiler::new()
+            .target("aarch64v8")
+            .target_abi("gnu")
+            .cpu("cortex-a53")
+            .opt_level(2)
+            .unwrap()
+            .size_level(1)
+            .unwrap()
+            .bounds_checks(true)
+            .simd(false)
+            .xip(true)
+            .proposal(Proposal::TailCall, true)
+            .proposal(Proposal::BulkMemory, true)
+            .proposal(Proposal::ReferenceTypes, false)
+            .multi_module(true);
+        assert_eq!(
+            compiler.args(),
+            vec![
+                "--target=aarch64v8",
+                "--target-abi=gnu",
+                "--cpu=cortex-a53",
+                "--opt-level=2",
+                "--size-level=1",
+                "--bounds-checks=1",
+                "--disable-simd",
+                "--xip",
+                "--disable-ref-types",
+                "--enable-tail-call",
+                "--enable-multi-module",
+            ]
+        );
+
+        assert!(matches!(
+            Compiler::new().opt_level(4),
+            Err(RuntimeError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn test_compiler_spawn_failure() {
+        let wamrc = env::temp_dir().join("wamr-aot-missing").join("wamrc");
+        let compiler = Compiler::with_path(&wamrc).opt_level(2).unwrap();
+        assert_eq!(compiler.wamrc, wamrc);
+        assert_eq!(compiler.args(), vec!["--opt-level=2"]);
+        assert!(matches!(
+            compiler.compile(b"\0asm\x01\0\0\0"),
+            Err(RuntimeError::CompilationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_temp_dir() {
+        let dir = TempDir::new().unwrap();
+        let other = TempDir::new().unwrap();
+        assert_ne!(dir.path(), other.path());
+        assert!(dir.path().is_dir());
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_compiler_compile() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "add") (param i32 i32) (result i32)
+        //     (local.get 0)
+        //     (local.get 1)
+        //     (i32.add)
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f,
+            0x7f, 0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+            0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+        ];
+        let aot = Compiler::new()
+            .bounds_checks(true)
+            .compile(&binary)
+            .unwrap();
+        assert_eq!(&aot[..4], b"\0aot");
+
+        let module = Module::from_vec(&runtime, aot, "add").unwrap();
+        let instance = &Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(instance, "add").unwrap();
+        let result = function.call(instance, &vec![WasmValue::I32(3), WasmValue::I32(4)]);
+        assert_eq!(result.unwrap(), vec![WasmValue::I32(7)]);
+        assert_eq!(instance.running_mode(), None);
+
+        let invalid = Compiler::new().compile(&binary[..20]);
+        assert!(matches!(invalid, Err(RuntimeError::CompilationError(_))));
+    }
+}
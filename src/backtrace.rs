@@ -0,0 +1,274 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! the wasm call stack when a function traps.
+//! it is captured only with the `dump-call-stack` feature, see `ExecError::frames`.
+//! with the `dwarf` and `classic-interp` features, frames are mapped to source locations
+//! by the DWARF in `.debug_*` custom sections of the module
+
+use std::{collections::BTreeMap, fmt};
+
+/// A wasm function on the call stack, the innermost first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// the function index, including imported functions
+    pub func_index: Option<u32>,
+    /// the name from the name section or the export
+    pub func_name: Option<String>,
+    /// the offset of the instruction, as reported by WAMR
+    pub instr_offset: u32,
+    pub module_name: String,
+    /// where the instruction is in the source code, the innermost inlined function
+    /// first. empty without the `dwarf` and `classic-interp` features or debug info
+    pub locations: Vec<SourceLocation>,
+}
+
+/// A position in the source code, resolved from DWARF
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// the demangled function name
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file.as_deref().unwrap_or("<unknown>"))?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.func_name, self.func_index) {
+            (Some(name), _) => write!(f, "{}!{}", self.module_name, name)?,
+            (None, Some(index)) => write!(f, "{}!$f{}", self.module_name, index)?,
+            (None, None) => write!(f, "{}!<unknown>", self.module_name)?,
+        }
+        write!(f, " @ 0x{:04x}", self.instr_offset)
+    }
+}
+
+/// write frames like a Rust backtrace. every inlined function is a line of its own
+///
+/// ```text
+///    0: plugin::parse
+///              at src/lib.rs:12:5
+///    1: plugin!$f3 @ 0x0c00
+/// ```
+pub(crate) fn write_frames(f: &mut fmt::Formatter, frames: &[Frame]) -> fmt::Result {
+    let mut index = 0;
+    for frame in frames {
+        if frame.locations.is_empty() {
+            write!(f, "\n{:>4}: {}", index, frame)?;
+            index += 1;
+            continue;
+        }
+
+        for location in &frame.locations {
+            match &location.function {
+                Some(function) => write!(f, "\n{:>4}: {}", index, function)?,
+                None => write!(f, "\n{:>4}: {}", index, frame)?,
+            }
+            write!(f, "\n             at {}", location)?;
+            index += 1;
+        }
+    }
+    Ok(())
+}
+
+/// parse the output of `wasm_runtime_dump_call_stack_to_buf()`. like
+///
+/// ```text
+/// #00: 0x0a2c - $f12
+/// #01: 0x0b04 - _start
+/// ```
+///
+/// `names` are function names by index, usually from the name section
+#[cfg_attr(not(feature = "dump-call-stack"), allow(dead_code))]
+pub(crate) fn parse_call_stack(
+    text: &str,
+    module_name: &str,
+    names: &BTreeMap<u32, String>,
+) -> Vec<Frame> {
+    text.lines()
+        .filter_map(|line| parse_frame(line.trim(), module_name, names))
+        .collect()
+}
+
+fn parse_frame(line: &str, module_name: &str, names: &BTreeMap<u32, String>) -> Option<Frame> {
+    let (_, rest) = line.strip_prefix('#')?.split_once(": ")?;
+    let (offset, func) = rest.split_once(" - ")?;
+    let instr_offset = u32::from_str_radix(offset.strip_prefix("0x")?, 16).ok()?;
+
+    let (func_index, func_name) = match func
+        .strip_prefix("$f")
+        .and_then(|index| index.parse::<u32>().ok())
+    {
+        Some(index) => (Some(index), names.get(&index).cloned()),
+        None => (func_index_of(names, func), Some(String::from(func))),
+    };
+
+    Some(Frame {
+        func_index,
+        func_name,
+        instr_offset,
+        module_name: String::from(module_name),
+        locations: Vec::new(),
+    })
+}
+
+/// the index of the function with the name. only trust a unique name
+#[cfg_attr(
+    not(any(feature = "dump-call-stack", feature = "perf-profiling")),
+    allow(dead_code)
+)]
+pub(crate) fn func_index_of(names: &BTreeMap<u32, String>, name: &str) -> Option<u32> {
+    let mut indexes = names
+        .iter()
+        .filter(|(_, n)| n.as_str() == name)
+        .map(|(index, _)| *index);
+    match (indexes.next(), indexes.next()) {
+        (Some(index), None) => Some(index),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_call_stack() {
+        let text = "\n#00: 0x0a2c - $f12\n#01: 0x0b04 - run\n#02: 0x0c00 - $f3\n";
+        let names = BTreeMap::from([(3, String::from("main")), (7, String::from("run"))]);
+
+        let frames = parse_call_stack(text, "plugin", &names);
+        assert_eq!(
+            frames,
+            vec![
+                Frame {
+                    func_index: Some(12),
+                    func_name: None,
+                    instr_offset: 0x0a2c,
+                    module_name: String::from("plugin"),
+                    locations: vec![],
+                },
+                Frame {
+                    func_index: Some(7),
+                    func_name: Some(String::from("run")),
+                    instr_offset: 0x0b04,
+                    module_name: String::from("plugin"),
+                    locations: vec![],
+                },
+                Frame {
+                    func_index: Some(3),
+                    func_name: Some(String::from("main")),
+                    instr_offset: 0x0c00,
+                    module_name: String::from("plugin"),
+                    locations: vec![],
+                },
+            ]
+        );
+        assert_eq!(frames[0].to_string(), "plugin!$f12 @ 0x0a2c");
+        assert_eq!(frames[1].to_string(), "plugin!run @ 0x0b04");
+    }
+
+    #[test]
+    fn test_write_frames() {
+        struct Backtrace(Vec<Frame>);
+
+        impl fmt::Display for Backtrace {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write_frames(f, &self.0)
+            }
+        }
+
+        let frames = vec![
+            Frame {
+                func_index: Some(12),
+                func_name: None,
+                instr_offset: 0x0a2c,
+                module_name: String::from("plugin"),
+                locations: vec![
+                    SourceLocation {
+                        function: Some(String::from("plugin::parse")),
+                        file: Some(String::from("src/lib.rs")),
+                        line: Some(12),
+                        column: Some(5),
+                    },
+                    SourceLocation {
+                        function: Some(String::from("plugin::run")),
+                        file: Some(String::from("src/lib.rs")),
+                        line: Some(30),
+                        column: None,
+                    },
+                ],
+            },
+            Frame {
+                func_index: Some(3),
+                func_name: None,
+                instr_offset: 0x0c00,
+                module_name: String::from("plugin"),
+                locations: vec![],
+            },
+        ];
+
+        assert_eq!(
+            Backtrace(frames).to_string(),
+            "\n   0: plugin::parse\n             at src/lib.rs:12:5\
+             \n   1: plugin::run\n             at src/lib.rs:30\
+             \n   2: plugin!$f3 @ 0x0c00"
+        );
+    }
+
+    #[test]
+    fn test_parse_call_stack_invalid() {
+        assert!(parse_call_stack("", "plugin", &BTreeMap::new()).is_empty());
+        assert!(parse_call_stack("#00 0x0a2c $f12", "plugin", &BTreeMap::new()).is_empty());
+    }
+
+    #[cfg(feature = "dump-call-stack")]
+    #[test]
+    fn test_trap_frames() {
+        use crate::{
+            function::Function, instance::Instance, module::Module, runtime::Runtime, trap::Trap,
+            RuntimeError,
+        };
+
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func $inner unreachable)
+        //   (func (export "outer") (call $inner))
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x03, 0x02, 0x00, 0x00, 0x07, 0x09, 0x01, 0x05, 0x6f, 0x75, 0x74, 0x65, 0x72,
+            0x00, 0x01, 0x0a, 0x0a, 0x02, 0x03, 0x00, 0x00, 0x0b, 0x04, 0x00, 0x10, 0x00, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "frames").unwrap();
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(&instance, "outer").unwrap();
+
+        let error = match function.call(&instance, &vec![]) {
+            Err(RuntimeError::ExecutionError(error)) => error,
+            other => panic!("expect a trap, got {:?}", other),
+        };
+        assert_eq!(error.trap, Trap::Unreachable);
+        assert_eq!(error.frames.len(), 2);
+        assert_eq!(error.frames[0].func_index, Some(0));
+        assert_eq!(error.frames[1].func_name.as_deref(), Some("outer"));
+        assert_eq!(error.frames[1].module_name, "frames");
+    }
+}
@@ -0,0 +1,445 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! a minimal reader of the .wasm binary format, for the information
+//! WAMR doesn't expose via `wasm_export.h`
+
+use std::{collections::BTreeMap, ops::Range};
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_IMPORT: u8 = 2;
+const SECTION_TABLE: u8 = 4;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_EXPORT: u8 = 7;
+const SECTION_CODE: u8 = 10;
+
+const IMPORT_FUNC: u8 = 0x00;
+const IMPORT_TABLE: u8 = 0x01;
+const IMPORT_MEMORY: u8 = 0x02;
+const IMPORT_GLOBAL: u8 = 0x03;
+const IMPORT_TAG: u8 = 0x04;
+
+const EXPORT_FUNC: u8 = 0x00;
+
+const NAME_SUBSECTION_FUNCTION: u8 = 1;
+
+/// limits of a memory (in pages) or a table (in elements)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+/// limits of all memories and tables, imported ones first
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeclaredLimits {
+    pub memories: Vec<Limits>,
+    pub tables: Vec<Limits>,
+}
+
+pub struct Reader<'a> {
+    content: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(content: &'a [u8]) -> Self {
+        Reader { content, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.content.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .content
+            .get(self.offset)
+            .ok_or_else(|| format!("unexpected end at offset {}", self.offset))?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.content.len())
+            .ok_or_else(|| format!("unexpected end at offset {}", self.offset))?;
+        let bytes = &self.content[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn read_leb_u64(&mut self) -> Result<u64, String> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift >= 64 {
+                return Err(format!("integer too large at offset {}", self.offset));
+            }
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn read_leb_u32(&mut self) -> Result<u32, String> {
+        let value = self.read_leb_u64()?;
+        u32::try_from(value).map_err(|_| format!("integer too large at offset {}", self.offset))
+    }
+
+    pub fn read_name(&mut self) -> Result<&'a str, String> {
+        let len = self.read_leb_u32()? as usize;
+        let bytes = self.read_bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| format!("invalid name at offset {}", self.offset))
+    }
+
+    fn read_limits(&mut self) -> Result<Limits, String> {
+        // bit 0: has max, bit 1: shared, bit 2: 64-bit
+        let flags = self.read_u8()?;
+        let min = self.read_leb_u64()?;
+        let max = match flags & 0x01 {
+            0 => None,
+            _ => Some(self.read_leb_u64()?),
+        };
+        Ok(Limits { min, max })
+    }
+
+    fn skip_val_type(&mut self) -> Result<(), String> {
+        match self.read_u8()? {
+            // (ref null? heaptype) of function-references and GC
+            0x63 | 0x64 => self.read_leb_u64().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn read_table_type(&mut self) -> Result<Limits, String> {
+        self.skip_val_type()?;
+        self.read_limits()
+    }
+
+    fn read_import(&mut self) -> Result<Import, String> {
+        self.read_name()?;
+        self.read_name()?;
+        match self.read_u8()? {
+            IMPORT_FUNC => {
+                self.read_leb_u32()?;
+                Ok(Import::Func)
+            }
+            IMPORT_TABLE => Ok(Import::Table(self.read_table_type()?)),
+            IMPORT_MEMORY => Ok(Import::Memory(self.read_limits()?)),
+            IMPORT_GLOBAL => {
+                self.skip_val_type()?;
+                self.read_u8()?;
+                Ok(Import::Other)
+            }
+            IMPORT_TAG => {
+                self.read_u8()?;
+                self.read_leb_u32()?;
+                Ok(Import::Other)
+            }
+            kind => Err(format!("unknown import kind {}", kind)),
+        }
+    }
+}
+
+enum Import {
+    Func,
+    Table(Limits),
+    Memory(Limits),
+    Other,
+}
+
+/// iterate over (section id, section content) of a .wasm
+pub fn sections(content: &[u8]) -> Result<Vec<(u8, Reader<'_>)>, String> {
+    let mut reader = Reader::new(content);
+    if reader.read_bytes(WASM_MAGIC.len())? != WASM_MAGIC {
+        return Err(String::from("not a .wasm binary"));
+    }
+    // version
+    reader.read_bytes(4)?;
+
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.read_u8()?;
+        let size = reader.read_leb_u32()? as usize;
+        let start = reader.offset();
+        reader.read_bytes(size)?;
+        sections.push((
+            id,
+            Reader {
+                content: &content[..start + size],
+                offset: start,
+            },
+        ));
+    }
+    Ok(sections)
+}
+
+pub fn is_wasm_binary(content: &[u8]) -> bool {
+    content.starts_with(WASM_MAGIC)
+}
+
+/// collect the limits of imported and defined memories and tables
+pub fn declared_limits(content: &[u8]) -> Result<DeclaredLimits, String> {
+    let mut limits = DeclaredLimits::default();
+
+    for (id, mut reader) in sections(content)? {
+        match id {
+            SECTION_IMPORT => {
+                let count = reader.read_leb_u32()?;
+                for _ in 0..count {
+                    match reader.read_import()? {
+                        Import::Table(table) => limits.tables.push(table),
+                        Import::Memory(memory) => limits.memories.push(memory),
+                        _ => {}
+                    }
+                }
+            }
+            SECTION_TABLE => {
+                let count = reader.read_leb_u32()?;
+                for _ in 0..count {
+                    limits.tables.push(reader.read_table_type()?);
+                }
+            }
+            SECTION_MEMORY => {
+                let count = reader.read_leb_u32()?;
+                for _ in 0..count {
+                    limits.memories.push(reader.read_limits()?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(limits)
+}
+
+/// the function names in the "name" custom section, by function index.
+/// empty if there isn't one
+#[cfg_attr(
+    not(any(feature = "dump-call-stack", feature = "perf-profiling")),
+    allow(dead_code)
+)]
+pub fn function_names(content: &[u8]) -> Result<BTreeMap<u32, String>, String> {
+    let mut names = BTreeMap::new();
+
+    for (id, mut reader) in sections(content)? {
+        if id != SECTION_CUSTOM || reader.read_name()? != "name" {
+            continue;
+        }
+
+        while !reader.is_empty() {
+            let subsection_id = reader.read_u8()?;
+            let size = reader.read_leb_u32()? as usize;
+            let mut subsection = Reader::new(reader.read_bytes(size)?);
+            if subsection_id != NAME_SUBSECTION_FUNCTION {
+                continue;
+            }
+
+            let count = subsection.read_leb_u32()?;
+            for _ in 0..count {
+                let index = subsection.read_leb_u32()?;
+                let name = subsection.read_name()?;
+                names.insert(index, String::from(name));
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// the payload of custom sections by name, like ".debug_info"
+#[cfg_attr(not(feature = "dwarf"), allow(dead_code))]
+pub fn custom_sections(content: &[u8]) -> Result<BTreeMap<&str, &[u8]>, String> {
+    let mut custom_sections = BTreeMap::new();
+
+    for (id, mut reader) in sections(content)? {
+        if id != SECTION_CUSTOM {
+            continue;
+        }
+
+        let name = reader.read_name()?;
+        let payload = &reader.content[reader.offset..];
+        custom_sections.insert(name, payload);
+    }
+
+    Ok(custom_sections)
+}
+
+/// the offset of the code section payload in the binary. DWARF addresses
+/// of wasm are relative to it
+#[cfg_attr(not(feature = "dwarf"), allow(dead_code))]
+pub fn code_section_offset(content: &[u8]) -> Result<Option<usize>, String> {
+    Ok(sections(content)?
+        .into_iter()
+        .find(|(id, _)| *id == SECTION_CODE)
+        .map(|(_, reader)| reader.offset()))
+}
+
+/// names of exported functions by function index
+#[cfg_attr(
+    not(any(feature = "dump-call-stack", feature = "perf-profiling")),
+    allow(dead_code)
+)]
+pub fn export_function_names(content: &[u8]) -> Result<BTreeMap<u32, String>, String> {
+    let mut names = BTreeMap::new();
+
+    for (id, mut reader) in sections(content)? {
+        if id != SECTION_EXPORT {
+            continue;
+        }
+
+        let count = reader.read_leb_u32()?;
+        for _ in 0..count {
+            let name = reader.read_name()?;
+            let kind = reader.read_u8()?;
+            let index = reader.read_leb_u32()?;
+            if kind == EXPORT_FUNC {
+                names.entry(index).or_insert_with(|| String::from(name));
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// the range of every function body in the binary, by function index.
+/// imported functions have no body
+pub fn function_bodies(content: &[u8]) -> Result<BTreeMap<u32, Range<usize>>, String> {
+    let mut bodies = BTreeMap::new();
+    let mut imported_functions = 0;
+
+    for (id, mut reader) in sections(content)? {
+        match id {
+            SECTION_IMPORT => {
+                let count = reader.read_leb_u32()?;
+                for _ in 0..count {
+                    if let Import::Func = reader.read_import()? {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            SECTION_CODE => {
+                let count = reader.read_leb_u32()?;
+                for index in 0..count {
+                    let size = reader.read_leb_u32()? as usize;
+                    let start = reader.offset();
+                    reader.read_bytes(size)?;
+                    bodies.insert(imported_functions + index, start..start + size);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(bodies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_leb() {
+        let mut reader = Reader::new(&[0xe5, 0x8e, 0x26, 0x7f]);
+        assert_eq!(reader.read_leb_u32(), Ok(624485));
+        assert_eq!(reader.read_leb_u32(), Ok(127));
+        assert!(reader.read_leb_u32().is_err());
+    }
+
+    #[test]
+    fn test_declared_limits() {
+        // (module
+        //   (import "env" "mem" (memory 1 2))
+        //   (table 3 funcref)
+        //   (memory 4)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x02, 0x0d, 0x01, 0x03, 0x65, 0x6e,
+            0x76, 0x03, 0x6d, 0x65, 0x6d, 0x02, 0x01, 0x01, 0x02, 0x04, 0x04, 0x01, 0x70, 0x00,
+            0x03, 0x05, 0x03, 0x01, 0x00, 0x04,
+        ];
+
+        let limits = declared_limits(&binary).unwrap();
+        assert_eq!(
+            limits.memories,
+            vec![
+                Limits {
+                    min: 1,
+                    max: Some(2)
+                },
+                Limits { min: 4, max: None }
+            ]
+        );
+        assert_eq!(limits.tables, vec![Limits { min: 3, max: None }]);
+    }
+
+    #[test]
+    fn test_function_names() {
+        // (module
+        //   (func $a)
+        //   (func $b)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x03, 0x02, 0x00, 0x00, 0x0a, 0x07, 0x02, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x0b,
+            0x00, 0x0e, 0x04, 0x6e, 0x61, 0x6d, 0x65, 0x01, 0x07, 0x02, 0x00, 0x01, 0x61, 0x01,
+            0x01, 0x62,
+        ];
+
+        let names = function_names(&binary).unwrap();
+        assert_eq!(
+            names,
+            BTreeMap::from([(0, String::from("a")), (1, String::from("b"))])
+        );
+
+        assert!(function_names(&binary[..28]).unwrap().is_empty());
+
+        let custom_sections = custom_sections(&binary).unwrap();
+        assert_eq!(custom_sections.len(), 1);
+        assert_eq!(custom_sections["name"].len(), 9);
+
+        assert_eq!(code_section_offset(&binary), Ok(Some(21)));
+    }
+
+    #[test]
+    fn test_functions() {
+        // (module
+        //   (import "env" "f" (func))
+        //   (func (export "g"))
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00, 0x03, 0x02, 0x01,
+            0x00, 0x07, 0x05, 0x01, 0x01, 0x67, 0x00, 0x01, 0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+        ];
+
+        assert_eq!(
+            export_function_names(&binary).unwrap(),
+            BTreeMap::from([(1, String::from("g"))])
+        );
+        assert_eq!(
+            function_bodies(&binary).unwrap(),
+            BTreeMap::from([(1, 40..42)])
+        );
+    }
+
+    #[test]
+    fn test_not_wasm_binary() {
+        assert!(!is_wasm_binary(b"\0aot"));
+        assert!(declared_limits(b"\0aot\x01\0\0\0").is_err());
+    }
+}
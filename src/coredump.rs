@@ -0,0 +1,401 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! write the state of an instance as a Wasm coredump, defined in
+//! [tool-conventions](https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md).
+//!
+//! A coredump contains the default linear memory, exported globals and the call stack.
+//! Frames are only available with the `dump-call-stack` feature. Locals and operand
+//! stacks are always marked as missing.
+//!
+//! Only exported globals are captured, WAMR doesn't expose the others. So the global
+//! indexes in a coredump don't match those of the module unless all globals are exported.
+//!
+//! Code offsets come from the classic interpreter. The fast interpreter reports offsets
+//! in its own rewritten code, which are written as `UNKNOWN_CODE_OFFSET`.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use wamr_sys::{
+    wasm_export_t, wasm_global_inst_t, wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_GLOBAL,
+    wasm_runtime_get_export_count, wasm_runtime_get_export_global_inst,
+    wasm_runtime_get_export_type,
+};
+
+use crate::{
+    backtrace::Frame,
+    binary::{function_bodies, is_wasm_binary},
+    instance::Instance,
+    value::{WasmValType, WasmValue},
+};
+
+const SECTION_CUSTOM: u8 = 0;
+const SECTION_MEMORY: u8 = 5;
+const SECTION_GLOBAL: u8 = 6;
+const SECTION_DATA: u8 = 11;
+
+/// the code offset of frames whose offset isn't one in the function body
+pub const UNKNOWN_CODE_OFFSET: u32 = u32::MAX;
+
+/// the state of an exported global
+struct Global {
+    value: WasmValue,
+    mutable: bool,
+}
+
+/// write a coredump of `instance` with `frames` to `path`, which must not exist
+pub fn write_coredump(instance: &Instance, frames: &[Frame], path: &Path) -> std::io::Result<()> {
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(&build_coredump(instance, frames))
+}
+
+/// build a coredump of `instance` with `frames`, the innermost first
+pub fn build_coredump(instance: &Instance, frames: &[Frame]) -> Vec<u8> {
+    let module = instance.get_module();
+    let memory = instance
+        .get_memory_page_count()
+        .map(|(cur_pages, max_pages)| {
+            (
+                cur_pages,
+                max_pages,
+                instance.copy_memory().unwrap_or_default(),
+            )
+        });
+    let globals = exported_globals(instance);
+
+    let content = module.get_content();
+    let bodies = if is_wasm_binary(content) {
+        function_bodies(content).unwrap_or_default()
+    } else {
+        Default::default()
+    };
+
+    let mut coredump = Vec::from(*b"\0asm\x01\0\0\0");
+
+    // process-info
+    let mut core = vec![0x00];
+    write_name(&mut core, module.get_name());
+    write_custom_section(&mut coredump, "core", &core);
+
+    let mut coremodules = Vec::new();
+    write_leb(&mut coremodules, 1);
+    coremodules.push(0x00);
+    write_name(&mut coremodules, module.get_name());
+    write_custom_section(&mut coredump, "coremodules", &coremodules);
+
+    // the only instance refers to all memories and globals in the coredump
+    let mut coreinstances = Vec::new();
+    write_leb(&mut coreinstances, 1);
+    coreinstances.push(0x00);
+    write_leb(&mut coreinstances, 0);
+    write_leb(&mut coreinstances, memory.is_some() as u64);
+    if memory.is_some() {
+        write_leb(&mut coreinstances, 0);
+    }
+    write_leb(&mut coreinstances, globals.len() as u64);
+    for index in 0..globals.len() {
+        write_leb(&mut coreinstances, index as u64);
+    }
+    write_custom_section(&mut coredump, "coreinstances", &coreinstances);
+
+    let mut corestack = vec![0x00];
+    write_name(&mut corestack, "main");
+    let frames = frames
+        .iter()
+        .filter(|frame| frame.func_index.is_some())
+        .collect::<Vec<&Frame>>();
+    write_leb(&mut corestack, frames.len() as u64);
+    for frame in frames {
+        let func_index = frame.func_index.unwrap();
+        // offsets in the module binary, like those of the classic interpreter,
+        // become offsets in the function body. others are kept as they are
+        let code_offset = match bodies.get(&func_index) {
+            // they don't point into the binary
+            _ if wamr_sys::WAMR_BUILD_FAST_INTERP => UNKNOWN_CODE_OFFSET,
+            Some(body) if body.contains(&(frame.instr_offset as usize)) => {
+                frame.instr_offset - body.start as u32
+            }
+            _ => frame.instr_offset,
+        };
+
+        corestack.push(0x00);
+        write_leb(&mut corestack, 0);
+        write_leb(&mut corestack, func_index as u64);
+        write_leb(&mut corestack, code_offset as u64);
+        // no locals and no operand stack
+        write_leb(&mut corestack, 0);
+        write_leb(&mut corestack, 0);
+    }
+    write_custom_section(&mut coredump, "corestack", &corestack);
+
+    if let Some((cur_pages, max_pages, _)) = &memory {
+        let mut section = Vec::new();
+        write_leb(&mut section, 1);
+        section.push(0x01);
+        write_leb(&mut section, *cur_pages);
+        write_leb(&mut section, *max_pages);
+        write_section(&mut coredump, SECTION_MEMORY, &section);
+    }
+
+    if !globals.is_empty() {
+        let mut section = Vec::new();
+        write_leb(&mut section, globals.len() as u64);
+        for global in &globals {
+            write_global(&mut section, global);
+        }
+        write_section(&mut coredump, SECTION_GLOBAL, &section);
+    }
+
+    if let Some((_, _, data)) = &memory {
+        let mut section = Vec::new();
+        write_leb(&mut section, 1);
+        // active, memory 0, (i32.const 0)
+        section.extend([0x00, 0x41, 0x00, 0x0b]);
+        write_leb(&mut section, data.len() as u64);
+        section.extend(data);
+        write_section(&mut coredump, SECTION_DATA, &section);
+    }
+
+    coredump
+}
+
+fn exported_globals(instance: &Instance) -> Vec<Global> {
+    let module = instance.get_module().get_inner_module();
+    let export_count = unsafe { wasm_runtime_get_export_count(module) };
+
+    (0..export_count)
+        .filter_map(|index| unsafe {
+            let mut export = wasm_export_t::default();
+            wasm_runtime_get_export_type(module, index, &mut export);
+            if export.kind != wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_GLOBAL {
+                return None;
+            }
+
+            let mut global = wasm_global_inst_t::default();
+            if !wasm_runtime_get_export_global_inst(
+                instance.get_inner_instance(),
+                export.name,
+                &mut global,
+            ) {
+                return None;
+            }
+
+            read_global(&global).map(|value| Global {
+                value,
+                mutable: global.is_mutable,
+            })
+        })
+        .collect()
+}
+
+unsafe fn read_global(global: &wasm_global_inst_t) -> Option<WasmValue> {
+    let data = global.global_data;
+    match WasmValType::from_valkind(global.kind as u32) {
+        WasmValType::I32 => Some(WasmValue::I32((data as *const i32).read_unaligned())),
+        WasmValType::I64 => Some(WasmValue::I64((data as *const i64).read_unaligned())),
+        WasmValType::F32 => Some(WasmValue::F32((data as *const f32).read_unaligned())),
+        WasmValType::F64 => Some(WasmValue::F64((data as *const f64).read_unaligned())),
+        WasmValType::V128 => Some(WasmValue::V128((data as *const i128).read_unaligned())),
+        // references can't be restored from a coredump
+        _ => None,
+    }
+}
+
+fn write_global(buf: &mut Vec<u8>, global: &Global) {
+    let (val_type, const_op) = match global.value {
+        WasmValue::I32(_) => (0x7f, vec![0x41]),
+        WasmValue::I64(_) => (0x7e, vec![0x42]),
+        WasmValue::F32(_) => (0x7d, vec![0x43]),
+        WasmValue::F64(_) => (0x7c, vec![0x44]),
+        WasmValue::V128(_) => (0x7b, vec![0xfd, 0x0c]),
+        WasmValue::Void => unreachable!("a global always has a value"),
+    };
+
+    buf.push(val_type);
+    buf.push(global.mutable as u8);
+    buf.extend(const_op);
+    match global.value {
+        WasmValue::I32(value) => write_sleb(buf, value as i64),
+        WasmValue::I64(value) => write_sleb(buf, value),
+        WasmValue::F32(value) => buf.extend(value.to_le_bytes()),
+        WasmValue::F64(value) => buf.extend(value.to_le_bytes()),
+        WasmValue::V128(value) => buf.extend(value.to_le_bytes()),
+        WasmValue::Void => {}
+    }
+    buf.push(0x0b);
+}
+
+fn write_leb(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_sleb(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    write_leb(buf, name.len() as u64);
+    buf.extend(name.as_bytes());
+}
+
+fn write_section(buf: &mut Vec<u8>, id: u8, content: &[u8]) {
+    buf.push(id);
+    write_leb(buf, content.len() as u64);
+    buf.extend(content);
+}
+
+fn write_custom_section(buf: &mut Vec<u8>, name: &str, content: &[u8]) {
+    let mut section = Vec::new();
+    write_name(&mut section, name);
+    section.extend(content);
+    write_section(buf, SECTION_CUSTOM, &section);
+}
+
+/// the name of a coredump file, like *plugin.wasm-1700000000123-4242-0.coredump*.
+/// the process id and a sequence number tell apart coredumps of the same millisecond
+pub(crate) fn coredump_file_name(module_name: &str, timestamp_ms: u128) -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    let module_name = module_name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}.coredump",
+        module_name,
+        timestamp_ms,
+        std::process::id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        binary::{custom_sections, declared_limits, sections},
+        function::Function,
+        module::Module,
+        runtime::Runtime,
+        RuntimeError,
+    };
+
+    #[test]
+    fn test_write_leb() {
+        let mut buf = Vec::new();
+        write_leb(&mut buf, 624485);
+        assert_eq!(buf, vec![0xe5, 0x8e, 0x26]);
+
+        let mut buf = Vec::new();
+        write_sleb(&mut buf, -123456);
+        assert_eq!(buf, vec![0xc0, 0xbb, 0x78]);
+
+        let mut buf = Vec::new();
+        write_sleb(&mut buf, 64);
+        assert_eq!(buf, vec![0xc0, 0x00]);
+    }
+
+    #[test]
+    fn test_coredump_file_name() {
+        let prefix = format!("plugins_a.wasm-1700000000123-{}-", std::process::id());
+
+        let first = coredump_file_name("plugins/a.wasm", 1700000000123);
+        let second = coredump_file_name("plugins/a.wasm", 1700000000123);
+        assert!(first.starts_with(&prefix), "{}", first);
+        assert!(first.ends_with(".coredump"), "{}", first);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_build_coredump() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (memory (export "memory") 1 2)
+        //   (global (export "counter") (mut i32) (i32.const 42))
+        //   (func (export "crash")
+        //     (i32.store (i32.const 16) (i32.const 7))
+        //     (unreachable)
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x04, 0x01, 0x01, 0x01, 0x02, 0x06, 0x06, 0x01, 0x7f,
+            0x01, 0x41, 0x2a, 0x0b, 0x07, 0x1c, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79,
+            0x02, 0x00, 0x07, 0x63, 0x6f, 0x75, 0x6e, 0x74, 0x65, 0x72, 0x03, 0x00, 0x05, 0x63,
+            0x72, 0x61, 0x73, 0x68, 0x00, 0x00, 0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x41, 0x10, 0x41,
+            0x07, 0x36, 0x02, 0x00, 0x00, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "crash").unwrap();
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(&instance, "crash").unwrap();
+
+        let frames = match function.call(&instance, &vec![]) {
+            Err(RuntimeError::ExecutionError(error)) => error.frames,
+            other => panic!("expect a trap, got {:?}", other),
+        };
+
+        let coredump = build_coredump(&instance, &frames);
+
+        let custom_sections = custom_sections(&coredump).unwrap();
+        for name in ["core", "coremodules", "coreinstances", "corestack"] {
+            assert!(custom_sections.contains_key(name), "{}", name);
+        }
+
+        let limits = declared_limits(&coredump).unwrap();
+        assert_eq!(limits.memories.len(), 1);
+        assert_eq!(limits.memories[0].min, 1);
+        assert_eq!(limits.memories[0].max, Some(2));
+
+        let mut data = sections(&coredump)
+            .unwrap()
+            .into_iter()
+            .find(|(id, _)| *id == SECTION_DATA)
+            .unwrap()
+            .1;
+        assert_eq!(data.read_leb_u32(), Ok(1));
+        assert_eq!(data.read_bytes(4).unwrap(), &[0x00, 0x41, 0x00, 0x0b]);
+        assert_eq!(data.read_leb_u32(), Ok(65536));
+        let memory = data.read_bytes(65536).unwrap();
+        assert_eq!(&memory[16..20], &[7, 0, 0, 0]);
+
+        let mut globals = sections(&coredump)
+            .unwrap()
+            .into_iter()
+            .find(|(id, _)| *id == SECTION_GLOBAL)
+            .unwrap()
+            .1;
+        assert_eq!(globals.read_leb_u32(), Ok(1));
+        assert_eq!(
+            globals.read_bytes(5).unwrap(),
+            &[0x7f, 0x01, 0x41, 0x2a, 0x0b]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! map frames to source locations with the DWARF in `.debug_*` custom sections.
+//!
+//! DWARF addresses of wasm are offsets in the code section. The classic interpreter
+//! reports offsets in the module binary, which are turned into addresses by subtracting
+//! the offset of the code section. The fast interpreter, the default one, reports offsets
+//! in its own code, which can't be mapped. So the `dwarf` feature requires the
+//! `classic-interp` one, and doesn't build without it.
+
+use std::borrow::Cow;
+
+use addr2line::{
+    gimli::{self, EndianSlice, LittleEndian},
+    Context,
+};
+
+use crate::{
+    backtrace::{Frame, SourceLocation},
+    binary::{code_section_offset, custom_sections, is_wasm_binary},
+};
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+// instead of quietly finding no source location
+const _: () = assert!(
+    !wamr_sys::WAMR_BUILD_FAST_INTERP,
+    "the `dwarf` feature requires the `classic-interp` feature"
+);
+
+/// fill `locations` of frames. a module without valid DWARF leaves them empty
+pub fn symbolize(content: &[u8], frames: &mut [Frame]) {
+    if !is_wasm_binary(content) {
+        return;
+    }
+
+    let Ok(Some(code_offset)) = code_section_offset(content) else {
+        return;
+    };
+    let Ok(sections) = custom_sections(content) else {
+        return;
+    };
+    if !sections.keys().any(|name| name.starts_with(".debug_")) {
+        return;
+    }
+
+    let dwarf = gimli::Dwarf::load(|id| -> Result<Reader, gimli::Error> {
+        let section = sections.get(id.name()).copied().unwrap_or_default();
+        Ok(EndianSlice::new(section, LittleEndian))
+    });
+    let Ok(context) = dwarf.and_then(Context::from_dwarf) else {
+        return;
+    };
+
+    for frame in frames {
+        if let Some(address) = (frame.instr_offset as usize).checked_sub(code_offset) {
+            frame.locations = find_locations(&context, address as u64).unwrap_or_default();
+        }
+    }
+}
+
+fn find_locations(
+    context: &Context<Reader>,
+    address: u64,
+) -> Result<Vec<SourceLocation>, gimli::Error> {
+    let mut locations = Vec::new();
+
+    let mut frames = context.find_frames(address).skip_all_loads()?;
+    while let Some(frame) = frames.next()? {
+        let function = frame
+            .function
+            .as_ref()
+            .and_then(|name| name.demangle().ok())
+            .map(Cow::into_owned);
+        let location = frame.location.as_ref();
+
+        locations.push(SourceLocation {
+            function,
+            file: location.and_then(|l| l.file).map(String::from),
+            line: location.and_then(|l| l.line),
+            column: location.and_then(|l| l.column),
+        });
+    }
+
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbolize_without_debug_info() {
+        // (module
+        //   (func $a)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+        ];
+
+        let mut frames = vec![Frame {
+            func_index: Some(0),
+            func_name: None,
+            instr_offset: 0x16,
+            module_name: String::from("a"),
+            locations: vec![],
+        }];
+        symbolize(&binary, &mut frames);
+        assert!(frames[0].locations.is_empty());
+    }
+}
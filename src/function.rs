@@ -6,22 +6,22 @@
 //! an exported wasm function.
 //! get one via `Function::find_export_func()`
 
-use std::{ffi::CString, marker::PhantomData};
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use std::{ffi::CString, marker::PhantomData, time::Duration};
 use wamr_sys::{
     wasm_exec_env_t, wasm_func_get_param_count, wasm_func_get_result_count,
     wasm_func_get_result_types, wasm_function_inst_t, wasm_runtime_call_wasm,
-    wasm_runtime_get_exception, wasm_runtime_get_exec_env_singleton,
-    wasm_runtime_get_wasi_exit_code, wasm_runtime_lookup_function,
+    wasm_runtime_get_exec_env_singleton, wasm_runtime_lookup_function,
     wasm_valkind_enum_WASM_EXTERNREF, wasm_valkind_enum_WASM_F32, wasm_valkind_enum_WASM_F64,
     wasm_valkind_enum_WASM_FUNCREF, wasm_valkind_enum_WASM_I32, wasm_valkind_enum_WASM_I64,
     wasm_valkind_enum_WASM_V128,
 };
 
-use crate::{
-    helper::exception_to_string, instance::Instance, value::WasmValue, ExecError, RuntimeError,
-};
+use crate::{instance::Instance, trap::Trap, value::WasmValue, ExecError, RuntimeError};
 
 pub struct Function<'instance> {
+    name: String,
     function: wasm_function_inst_t,
     _phantom: PhantomData<Instance<'instance>>,
 }
@@ -36,18 +36,24 @@ impl<'instance> Function<'instance> {
         instance: &'instance Instance<'instance>,
         name: &str,
     ) -> Result<Self, RuntimeError> {
-        let name = CString::new(name).expect("CString::new failed");
+        let name_c = CString::new(name).expect("CString::new failed");
         let function =
-            unsafe { wasm_runtime_lookup_function(instance.get_inner_instance(), name.as_ptr()) };
+            unsafe { wasm_runtime_lookup_function(instance.get_inner_instance(), name_c.as_ptr()) };
         match function.is_null() {
             true => Err(RuntimeError::FunctionNotFound),
             false => Ok(Function {
+                name: String::from(name),
                 function,
                 _phantom: PhantomData,
             }),
         }
     }
 
+    /// the export name
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     #[allow(non_upper_case_globals)]
     #[allow(non_snake_case)]
     fn parse_result(
@@ -107,14 +113,74 @@ impl<'instance> Function<'instance> {
     /// execute an export function.
     /// all parameters need to be wrapped in `WasmValue`
     ///
+    /// the default timeout of the instance, if any, applies.
+    ///
     /// # Error
     ///
     /// Return `RuntimeError::ExecutionError` if failed.
-    #[allow(non_upper_case_globals)]
+    /// Return `RuntimeError::Terminated` if terminated by a `TerminateHandle`.
+    /// Return `RuntimeError::Timeout` if the default timeout of the instance is reached.
+    /// Return `RuntimeError::OutOfFuel` if the call runs beyond the fuel limit of the instance.
+    /// Return `RuntimeError::Exit` if a WASI program calls `proc_exit` with a non-zero code.
+    /// `proc_exit(0)` is a success and returns no results.
     pub fn call(
         &self,
         instance: &'instance Instance<'instance>,
         params: &Vec<WasmValue>,
+    ) -> Result<Vec<WasmValue>, RuntimeError> {
+        self.call_with_deadline(instance, params, instance.get_default_timeout())
+    }
+
+    /// execute an export function within a wall-clock time limit.
+    /// a shared watchdog thread terminates the instance once `timeout` has passed.
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::Timeout` if the function runs beyond `timeout`.
+    /// Others are the same as `call()`
+    pub fn call_with_timeout(
+        &self,
+        instance: &'instance Instance<'instance>,
+        params: &[WasmValue],
+        timeout: Duration,
+    ) -> Result<Vec<WasmValue>, RuntimeError> {
+        self.call_with_deadline(instance, params, Some(timeout))
+    }
+
+    fn call_with_deadline(
+        &self,
+        instance: &'instance Instance<'instance>,
+        params: &[WasmValue],
+        timeout: Option<Duration>,
+    ) -> Result<Vec<WasmValue>, RuntimeError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "wamr.call",
+            module = instance.get_module().get_name(),
+            function = self.get_name(),
+            args = params.len(),
+            duration_us = tracing::field::Empty,
+            trap = tracing::field::Empty,
+            exit_code = tracing::field::Empty,
+            error = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let result = self.call_inner(instance, params, timeout);
+
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&span, start, &result);
+        result
+    }
+
+    #[allow(non_upper_case_globals)]
+    fn call_inner(
+        &self,
+        instance: &'instance Instance<'instance>,
+        params: &[WasmValue],
+        timeout: Option<Duration>,
     ) -> Result<Vec<WasmValue>, RuntimeError> {
         let param_count =
             unsafe { wasm_func_get_param_count(self.function, instance.get_inner_instance()) };
@@ -122,6 +188,9 @@ impl<'instance> Function<'instance> {
             return Err(RuntimeError::ExecutionError(ExecError {
                 message: "invalid parameters".to_string(),
                 exit_code: 0xff,
+                trap: Trap::Unknown,
+                frames: Vec::new(),
+                coredump: None,
             }));
         }
 
@@ -137,23 +206,17 @@ impl<'instance> Function<'instance> {
         }
         argv.resize(capacity, 0);
 
-        let call_result: bool;
-        unsafe {
-            let exec_env: wasm_exec_env_t =
-                wasm_runtime_get_exec_env_singleton(instance.get_inner_instance());
-            call_result =
-                wasm_runtime_call_wasm(exec_env, self.function, param_count, argv.as_mut_ptr());
-        };
-
-        if !call_result {
-            unsafe {
-                let exception_c = wasm_runtime_get_exception(instance.get_inner_instance());
-                let error_info = ExecError {
-                    message: exception_to_string(exception_c),
-                    exit_code: wasm_runtime_get_wasi_exit_code(instance.get_inner_instance()),
-                };
-                return Err(RuntimeError::ExecutionError(error_info));
-            }
+        let exec_env: wasm_exec_env_t =
+            unsafe { wasm_runtime_get_exec_env_singleton(instance.get_inner_instance()) };
+        let call_result = instance.execute(timeout, exec_env, || unsafe {
+            wasm_runtime_call_wasm(exec_env, self.function, param_count, argv.as_mut_ptr())
+        });
+
+        match call_result {
+            Ok(()) => {}
+            // an exit with 0 is a success, without results
+            Err(RuntimeError::Exit(0)) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
         }
 
         // there is no out of bounds problem, because we have precalculated the safe vec size
@@ -164,10 +227,10 @@ impl<'instance> Function<'instance> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "wamrc")]
+    use crate::aot::Compiler;
     use crate::{module::Module, runtime::Runtime, wasi_context::WasiCtxBuilder};
-    use std::{
-        process::{Command, Stdio}, path::Path, path::PathBuf, env, fs,
-    };
+    use std::path::PathBuf;
 
     #[test]
     fn test_func_in_wasm32_unknown() {
@@ -214,6 +277,7 @@ mod tests {
         let function = Function::find_export_func(instance, "add");
         assert!(function.is_ok());
         let function = function.unwrap();
+        assert_eq!(function.get_name(), "add");
 
         let params: Vec<WasmValue> = vec![WasmValue::I64(10), WasmValue::I32(20)];
         let call_result = function.call(instance, &params);
@@ -304,6 +368,180 @@ mod tests {
     }
 
     #[test]
+    fn test_func_proc_exit() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        //   (memory (export "memory") 1)
+        //   (func (export "exit") (param i32)
+        //     (call $proc_exit (local.get 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x01, 0x7f,
+            0x00, 0x02, 0x24, 0x01, 0x16, 0x77, 0x61, 0x73, 0x69, 0x5f, 0x73, 0x6e, 0x61, 0x70,
+            0x73, 0x68, 0x6f, 0x74, 0x5f, 0x70, 0x72, 0x65, 0x76, 0x69, 0x65, 0x77, 0x31, 0x09,
+            0x70, 0x72, 0x6f, 0x63, 0x5f, 0x65, 0x78, 0x69, 0x74, 0x00, 0x00, 0x03, 0x02, 0x01,
+            0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x11, 0x02, 0x06, 0x6d, 0x65, 0x6d, 0x6f,
+            0x72, 0x79, 0x02, 0x00, 0x04, 0x65, 0x78, 0x69, 0x74, 0x00, 0x01, 0x0a, 0x08, 0x01,
+            0x06, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+        let mut module = Module::from_vec(&runtime, binary, "proc_exit").unwrap();
+        module.set_wasi_context(WasiCtxBuilder::new().build());
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(&instance, "exit").unwrap();
+        let result = function.call(&instance, &vec![WasmValue::I32(0)]);
+        assert_eq!(result.unwrap(), vec![]);
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(&instance, "exit").unwrap();
+        let result = function.call(&instance, &vec![WasmValue::I32(3)]);
+        assert!(matches!(result, Err(RuntimeError::Exit(3))));
+
+        // every exit counts, even with the same code as the previous one
+        let result = function.call(&instance, &vec![WasmValue::I32(3)]);
+        assert!(matches!(result, Err(RuntimeError::Exit(3))));
+        assert_eq!(instance.get_wasi_exit_code(), 3);
+
+        let result = function.call(&instance, &vec![WasmValue::I32(0)]);
+        assert_eq!(result.unwrap(), vec![]);
+        assert_eq!(instance.get_wasi_exit_code(), 0);
+
+        let result = function.call(&instance, &vec![WasmValue::I32(3)]);
+        assert!(matches!(result, Err(RuntimeError::Exit(3))));
+    }
+
+    #[test]
+    fn test_func_terminated() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "spin")
+        //     (loop (br 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x07, 0x08, 0x01, 0x04, 0x73, 0x70, 0x69, 0x6e, 0x00, 0x00,
+            0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+
+        let module = Module::from_vec(&runtime, binary, "spin");
+        assert!(module.is_ok());
+        let module = module.unwrap();
+
+        let instance = Instance::new(&runtime, &module, 1024);
+        assert!(instance.is_ok());
+        let instance: &Instance = &instance.unwrap();
+
+        let function = Function::find_export_func(instance, "spin");
+        assert!(function.is_ok());
+        let function = function.unwrap();
+
+        let handle = instance.terminate_handle();
+        let terminator = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            handle.terminate();
+        });
+
+        let result = function.call(instance, &vec![]);
+        assert!(matches!(result, Err(RuntimeError::Terminated)));
+
+        terminator.join().unwrap();
+    }
+
+    #[test]
+    fn test_func_timeout() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "spin")
+        //     (loop (br 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x07, 0x08, 0x01, 0x04, 0x73, 0x70, 0x69, 0x6e, 0x00, 0x00,
+            0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+
+        let module = Module::from_vec(&runtime, binary, "spin");
+        assert!(module.is_ok());
+        let module = module.unwrap();
+
+        let instance = Instance::new(&runtime, &module, 1024);
+        assert!(instance.is_ok());
+        let mut instance = instance.unwrap();
+
+        {
+            let instance: &Instance = &instance;
+            let function = Function::find_export_func(instance, "spin").unwrap();
+
+            let result = function.call_with_timeout(instance, &vec![], Duration::from_millis(100));
+            assert!(matches!(result, Err(RuntimeError::Timeout)));
+        }
+
+        instance.set_default_timeout(Some(Duration::from_millis(100)));
+        let instance: &Instance = &instance;
+        let function = Function::find_export_func(instance, "spin").unwrap();
+
+        let result = function.call(instance, &vec![]);
+        assert!(matches!(result, Err(RuntimeError::Timeout)));
+    }
+
+    #[test]
+    #[cfg(feature = "instruction-metering")]
+    fn test_func_out_of_fuel() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "spin")
+        //     (loop (br 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x07, 0x08, 0x01, 0x04, 0x73, 0x70, 0x69, 0x6e, 0x00, 0x00,
+            0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+
+        let module = Module::from_vec(&runtime, binary, "spin");
+        assert!(module.is_ok());
+        let module = module.unwrap();
+
+        let instance = Instance::new(&runtime, &module, 1024);
+        assert!(instance.is_ok());
+        let mut instance = instance.unwrap();
+        assert_eq!(instance.get_call_fuel_limit(), None);
+
+        assert!(matches!(
+            instance.set_call_fuel_limit(crate::instance::MAX_CALL_FUEL_LIMIT + 1),
+            Err(RuntimeError::InvalidConfiguration(_))
+        ));
+        assert_eq!(instance.get_call_fuel_limit(), None);
+
+        assert!(instance.set_call_fuel_limit(10_000).is_ok());
+        assert_eq!(instance.get_call_fuel_limit(), Some(10_000));
+
+        let instance: &Instance = &instance;
+        let function = Function::find_export_func(instance, "spin").unwrap();
+
+        // every call gets the whole limit
+        for _ in 0..2 {
+            let result = function.call(instance, &vec![]);
+            assert!(matches!(result, Err(RuntimeError::OutOfFuel)));
+            assert_eq!(instance.get_call_fuel_limit(), Some(10_000));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "wamrc")]
     fn test_func_in_multi_v128_return() {
         let runtime = Runtime::new().unwrap();
 
@@ -327,51 +565,10 @@ mod tests {
         wasm_src.push("multiret.wasm");
 
         // Compiling to AOT
-        let mut aot_dest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        aot_dest.push("resources/test");
-        aot_dest.push("multiret.aot");
-
-        // Get the path to wamrc binary
-        let base = match Path::new("target/release").exists() {
-            true => "target/release/build",
-            false => "target/debug/build",
-        };
-        let base_entries = fs::read_dir(base);
-        assert!(base_entries.is_ok());
-        let found = base_entries.unwrap()
-            .filter_map(|entry| entry.ok())
-            .map(|entry| {
-                let path = entry.path();
-                let name = path
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("")
-                    .to_string();
-                (path, name)
-            })
-            .filter_map(|(path, name)| {
-                if name.starts_with("wamr-sys") && path.join("out").join("wamrcbuild").join("bin").join("wamrc").exists() {
-                    Some(path.join("out").join("wamrcbuild").join("bin").join("wamrc"))
-                } else {
-                    None
-                }
-            })
-            .next();
-        assert!(found.is_some());
-        let wamrc_path = found.unwrap();
-
-        let wamrc_output = Command::new(wamrc_path)
-            .arg("--bounds-checks=1")
-            .arg("-o")
-            .arg(aot_dest.clone())
-            .arg(wasm_src.clone())
-            .stderr(Stdio::piped())  
-            .stdout(Stdio::piped())
-            .output()
-            .unwrap();
-        assert!(String::from_utf8_lossy(&wamrc_output.stdout).contains("Compile success"));
-
-        let module = Module::from_file(&runtime, aot_dest.as_path());
+        let aot = Compiler::new().bounds_checks(true).compile_file(&wasm_src);
+        assert!(aot.is_ok());
+
+        let module = Module::from_vec(&runtime, aot.unwrap(), "multiret");
         assert!(module.is_ok());
         let module = module.unwrap();
 
@@ -385,7 +582,7 @@ mod tests {
 
         let wrapped_result = function.call(instance, &vec![]);
         let unwrapped_result = wrapped_result.unwrap();
-        
+
         assert_eq!(unwrapped_result.len(), 12);
         assert_eq!(
             unwrapped_result,
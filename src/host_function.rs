@@ -5,11 +5,26 @@
 
 /// This is a wrapper of a host defined(Rust) function.
 use std::ffi::{c_void, CString};
-use std::ptr;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
 
-use wamr_sys::NativeSymbol;
+use wamr_sys::{
+    wasm_exec_env_t, wasm_runtime_get_function_attachment, wasm_runtime_get_module_inst,
+    wasm_runtime_set_exception, NativeSymbol,
+};
+
+use crate::{
+    trap::HOST_TRAP_PREFIX,
+    types::FuncType,
+    value::{WasmValType, WasmValue},
+};
 
-#[allow(dead_code)]
 #[derive(Debug)]
 struct HostFunction {
     function_name: CString,
@@ -51,6 +66,17 @@ impl HostFunctionList {
     pub fn get_module_name(&mut self) -> &CString {
         &self.module_name
     }
+
+    /// (name, function pointer) of every host function, in the order of registration
+    pub(crate) fn get_function_ptrs(&self) -> Vec<(String, usize)> {
+        self.host_functions
+            .iter()
+            .map(|f| {
+                let name = f.function_name.to_string_lossy().into_owned();
+                (name, f.function_ptr as usize)
+            })
+            .collect()
+    }
 }
 
 pub fn pack_host_function(function_name: &CString, function_ptr: *mut c_void) -> NativeSymbol {
@@ -62,11 +88,242 @@ pub fn pack_host_function(function_name: &CString, function_ptr: *mut c_void) ->
     }
 }
 
+type HostCallback = dyn Fn(&[WasmValue]) -> Result<WasmValue, String> + Send + Sync;
+
+/// A host function created at runtime, for example by an import resolver.
+///
+/// The callback receives the parameters of the call and returns the result,
+/// `WasmValue::Void` if the function has no result. Returning an `Err` raises
+/// a wasm exception with the message. It is reported as `Trap::HostTrap`.
+pub struct HostFunc {
+    callback: Box<HostCallback>,
+}
+
+impl HostFunc {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&[WasmValue]) -> Result<WasmValue, String> + Send + Sync + 'static,
+    {
+        HostFunc {
+            callback: Box::new(callback),
+        }
+    }
+}
+
+impl fmt::Debug for HostFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostFunc").finish_non_exhaustive()
+    }
+}
+
+type ImportResolverCallback = dyn Fn(&str, &str, &FuncType) -> Option<HostFunc> + Send + Sync;
+
+/// a callback to provide `HostFunc` for unlinked function imports
+pub struct ImportResolver {
+    callback: Box<ImportResolverCallback>,
+}
+
+impl ImportResolver {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&str, &str, &FuncType) -> Option<HostFunc> + Send + Sync + 'static,
+    {
+        ImportResolver {
+            callback: Box::new(callback),
+        }
+    }
+
+    /// only function types fit in raw native calls can be resolved.
+    /// every parameter takes one `uint64` slot and the result, if any, is written back
+    /// into the first slot
+    pub fn is_resolvable(func_type: &FuncType) -> bool {
+        let fit_in_slot = |t: &WasmValType| {
+            matches!(
+                t,
+                WasmValType::I32 | WasmValType::I64 | WasmValType::F32 | WasmValType::F64
+            )
+        };
+
+        func_type.results.len() <= 1
+            && func_type.params.iter().all(fit_in_slot)
+            && func_type.results.iter().all(fit_in_slot)
+    }
+
+    pub fn resolve(&self, module_name: &str, name: &str, func_type: &FuncType) -> Option<HostFunc> {
+        (self.callback)(module_name, name, func_type)
+    }
+}
+
+impl fmt::Debug for ImportResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImportResolver").finish_non_exhaustive()
+    }
+}
+
+// the attachment of a raw native symbol
+#[derive(Debug)]
+struct BoundHostFunc {
+    func_type: FuncType,
+    host_func: HostFunc,
+    #[cfg(feature = "tracing")]
+    module_name: String,
+    #[cfg(feature = "tracing")]
+    function_name: String,
+}
+
+/// Host functions resolved for one import module name. WAMR keeps pointers to
+/// the names and to the symbol array, so it has to be alive as long as the runtime.
+#[derive(Debug)]
+pub struct RawHostFunctionList {
+    module_name: CString,
+    function_names: Vec<CString>,
+    bound_functions: Vec<Box<BoundHostFunc>>,
+    native_symbols: Vec<NativeSymbol>,
+}
+
+// raw pointers in `native_symbols` point into the boxed content of the list itself
+unsafe impl Send for RawHostFunctionList {}
+unsafe impl Sync for RawHostFunctionList {}
+
+impl RawHostFunctionList {
+    pub fn new(module_name: &str) -> Self {
+        RawHostFunctionList {
+            module_name: CString::new(module_name).unwrap(),
+            function_names: Vec::new(),
+            bound_functions: Vec::new(),
+            native_symbols: Vec::new(),
+        }
+    }
+
+    pub fn register_host_function(
+        &mut self,
+        function_name: &str,
+        func_type: &FuncType,
+        host_func: HostFunc,
+    ) {
+        self.function_names
+            .push(CString::new(function_name).unwrap());
+        self.bound_functions.push(Box::new(BoundHostFunc {
+            func_type: func_type.clone(),
+            host_func,
+            #[cfg(feature = "tracing")]
+            module_name: self.module_name.to_string_lossy().into_owned(),
+            #[cfg(feature = "tracing")]
+            function_name: String::from(function_name),
+        }));
+
+        let name = self.function_names.last().unwrap();
+        let bound = self.bound_functions.last().unwrap();
+        self.native_symbols.push(NativeSymbol {
+            symbol: name.as_ptr(),
+            func_ptr: raw_host_function_trampoline as *mut c_void,
+            signature: ptr::null(),
+            attachment: &**bound as *const BoundHostFunc as *mut c_void,
+        });
+    }
+
+    pub fn get_native_symbols(&mut self) -> &mut Vec<NativeSymbol> {
+        &mut self.native_symbols
+    }
+
+    pub fn get_module_name(&self) -> &CString {
+        &self.module_name
+    }
+}
+
+unsafe extern "C" fn raw_host_function_trampoline(exec_env: wasm_exec_env_t, args: *mut u64) {
+    let bound = &*(wasm_runtime_get_function_attachment(exec_env) as *const BoundHostFunc);
+
+    // WAMR always reserves at least one slot for the result
+    let slot_count = std::cmp::max(bound.func_type.params.len(), 1);
+    let slots = slice::from_raw_parts_mut(args, slot_count);
+
+    // never unwind into C
+    let result = panic::catch_unwind(AssertUnwindSafe(|| call_bound(bound, slots)))
+        .unwrap_or_else(|payload| Err(format!("panicked: {}", panic_message(&*payload))));
+
+    if let Err(message) = result {
+        let message = CString::new(format!("{}{}", HOST_TRAP_PREFIX, message)).unwrap_or_default();
+        wasm_runtime_set_exception(wasm_runtime_get_module_inst(exec_env), message.as_ptr());
+    }
+}
+
+/// decode the parameters from `slots`, run the callback and write the result back
+fn call_bound(bound: &BoundHostFunc, slots: &mut [u64]) -> Result<(), String> {
+    let func_type = &bound.func_type;
+
+    let params = func_type
+        .params
+        .iter()
+        .zip(slots.iter())
+        .map(|(t, slot)| {
+            WasmValue::decode_from_raw_slot(*t, *slot)
+                .ok_or_else(|| format!("unsupported parameter type {:?}", t))
+        })
+        .collect::<Result<Vec<WasmValue>, String>>()?;
+
+    // host calls can be hot, keep them below the info level
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "wamr.host_call",
+        module = bound.module_name.as_str(),
+        function = bound.function_name.as_str(),
+        args = params.len(),
+        duration_us = tracing::field::Empty,
+        trap = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+    .entered();
+    #[cfg(feature = "tracing")]
+    let start = Instant::now();
+
+    let result = (bound.host_func.callback)(&params);
+
+    #[cfg(feature = "tracing")]
+    crate::trace::finish_host_call(&span, start, &result);
+
+    if let Some(slot) = encode_result(&result?, &func_type.results)? {
+        slots[0] = slot;
+    }
+    Ok(())
+}
+
+/// the slot of `result`, `None` for a function without results.
+/// fail if `result` doesn't match the declared `results`
+fn encode_result(result: &WasmValue, results: &[WasmValType]) -> Result<Option<u64>, String> {
+    let matched = match (results, result) {
+        ([], WasmValue::Void) => return Ok(None),
+        ([WasmValType::I32], WasmValue::I32(_))
+        | ([WasmValType::I64], WasmValue::I64(_))
+        | ([WasmValType::F32], WasmValue::F32(_))
+        | ([WasmValType::F64], WasmValue::F64(_)) => result.encode_to_raw_slot(),
+        _ => None,
+    };
+
+    matched.map(Some).ok_or_else(|| {
+        format!(
+            "host function returned {:?}, but {:?} is declared",
+            result, results
+        )
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        function::Function, instance::Instance, module::Module, runtime::Runtime, value::WasmValue,
+        function::Function, instance::Instance, module::Module, runtime::Runtime, trap::Trap,
+        RuntimeError,
     };
     use std::env;
     use std::path::PathBuf;
@@ -103,4 +360,115 @@ mod tests {
         let result = function.call(instance, &params);
         assert_eq!(result.unwrap(), vec![WasmValue::I32(116)]);
     }
+
+    #[test]
+    fn test_import_resolver_resolvable() {
+        let resolvable = FuncType {
+            params: vec![WasmValType::I32, WasmValType::F64],
+            results: vec![WasmValType::I64],
+        };
+        assert!(ImportResolver::is_resolvable(&resolvable));
+
+        let multi_results = FuncType {
+            params: vec![],
+            results: vec![WasmValType::I32, WasmValType::I32],
+        };
+        assert!(!ImportResolver::is_resolvable(&multi_results));
+
+        let v128 = FuncType {
+            params: vec![WasmValType::V128],
+            results: vec![],
+        };
+        assert!(!ImportResolver::is_resolvable(&v128));
+    }
+
+    #[test]
+    fn test_encode_result() {
+        assert_eq!(encode_result(&WasmValue::Void, &[]), Ok(None));
+        assert_eq!(
+            encode_result(&WasmValue::I32(-1), &[WasmValType::I32]),
+            Ok(Some(0xffff_ffff))
+        );
+        assert_eq!(
+            encode_result(&WasmValue::F64(1.0), &[WasmValType::F64]),
+            Ok(Some(1.0f64.to_bits()))
+        );
+
+        assert!(encode_result(&WasmValue::Void, &[WasmValType::I32]).is_err());
+        assert!(encode_result(&WasmValue::I64(1), &[WasmValType::I32]).is_err());
+        assert!(encode_result(&WasmValue::I32(1), &[]).is_err());
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "boom");
+
+        let payload = panic::catch_unwind(|| panic!("{}", 42)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "42");
+    }
+
+    #[test]
+    #[ignore]
+    fn test_import_resolver() {
+        let runtime = Runtime::builder()
+            .use_system_allocator()
+            .import_resolver(|module_name, name, func_type| {
+                assert_eq!(func_type.params.len(), 0);
+                match (module_name, name) {
+                    ("host", "extra") => Some(HostFunc::new(|_| Ok(WasmValue::I32(100)))),
+                    _ => None,
+                }
+            })
+            .build()
+            .unwrap();
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("add_extra_wasm32_wasi.wasm");
+        let module = Module::from_file(&runtime, d.as_path());
+        assert!(module.is_ok());
+        let module = module.unwrap();
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64);
+        assert!(instance.is_ok());
+        let instance: &Instance = &instance.unwrap();
+
+        let function = Function::find_export_func(instance, "add");
+        assert!(function.is_ok());
+        let function = function.unwrap();
+
+        let params: Vec<WasmValue> = vec![WasmValue::I32(8), WasmValue::I32(8)];
+        let result = function.call(instance, &params);
+        assert_eq!(result.unwrap(), vec![WasmValue::I32(116)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_import_resolver_host_trap() {
+        let runtime = Runtime::builder()
+            .use_system_allocator()
+            .import_resolver(|module_name, name, _| match (module_name, name) {
+                ("host", "extra") => Some(HostFunc::new(|_| panic!("extra is broken"))),
+                _ => None,
+            })
+            .build()
+            .unwrap();
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("add_extra_wasm32_wasi.wasm");
+        let module = Module::from_file(&runtime, d.as_path()).unwrap();
+        let instance = &Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let function = Function::find_export_func(instance, "add").unwrap();
+
+        let params: Vec<WasmValue> = vec![WasmValue::I32(8), WasmValue::I32(8)];
+        match function.call(instance, &params) {
+            Err(RuntimeError::ExecutionError(info)) => {
+                assert_eq!(info.trap, Trap::HostTrap);
+                assert!(info.message.contains("extra is broken"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
 }
@@ -8,22 +8,129 @@
 
 #![allow(unused_variables)]
 
-use core::{ffi::c_char, marker::PhantomData};
+use core::ffi::c_char;
+use std::cell::Cell;
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+#[cfg(feature = "memory-profiling")]
+use wamr_sys::wasm_runtime_dump_mem_consumption;
+#[cfg(feature = "instruction-metering")]
+use wamr_sys::wasm_runtime_set_instruction_count_limit;
+#[cfg(feature = "debug-interp")]
+use wamr_sys::wasm_runtime_start_debug_instance;
+use wamr_sys::{
+    wasm_application_execute_main, wasm_exec_env_t, wasm_memory_get_base_address,
+    wasm_memory_get_bytes_per_page, wasm_memory_get_cur_page_count, wasm_memory_get_max_page_count,
+    wasm_module_inst_t, wasm_runtime_call_wasm, wasm_runtime_clear_exception,
+    wasm_runtime_create_exec_env, wasm_runtime_deinstantiate, wasm_runtime_destroy_exec_env,
+    wasm_runtime_destroy_thread_env, wasm_runtime_get_default_memory, wasm_runtime_get_exception,
+    wasm_runtime_get_exec_env_singleton, wasm_runtime_get_running_mode,
+    wasm_runtime_get_wasi_exit_code, wasm_runtime_init_thread_env, wasm_runtime_instantiate_ex,
+    wasm_runtime_is_wasi_mode, wasm_runtime_lookup_wasi_start_function,
+    wasm_runtime_set_running_mode, wasm_runtime_terminate, InstantiationArgs,
+};
+#[cfg(feature = "dump-call-stack")]
+use wamr_sys::{wasm_runtime_dump_call_stack_to_buf, wasm_runtime_get_call_stack_buf_size};
+#[cfg(feature = "perf-profiling")]
 use wamr_sys::{
-    wasm_module_inst_t, wasm_runtime_deinstantiate, wasm_runtime_destroy_thread_env,
-    wasm_runtime_init_thread_env, wasm_runtime_instantiate,
+    wasm_runtime_dump_perf_profiling, wasm_runtime_get_wasm_func_exec_time,
+    wasm_runtime_sum_wasm_exec_time,
 };
 
+#[cfg(any(feature = "memory-profiling", feature = "perf-profiling"))]
+use crate::log_hook;
+#[cfg(feature = "memory-profiling")]
+use crate::memory_usage::{parse_memory_usage, MemoryUsage};
+#[cfg(feature = "perf-profiling")]
+use crate::profile::{millis, parse_perf_profiling, Profile};
+#[cfg(feature = "dump-call-stack")]
+use crate::{backtrace::parse_call_stack, helper::cstr_to_string};
 use crate::{
-    helper::error_buf_to_string, helper::DEFAULT_ERROR_BUF_SIZE, module::Module, runtime::Runtime,
-    RuntimeError,
+    backtrace::Frame,
+    coredump::{coredump_file_name, write_coredump},
+    helper::error_buf_to_string,
+    helper::exception_to_string,
+    helper::DEFAULT_ERROR_BUF_SIZE,
+    module::Module,
+    proc_exit,
+    runtime::{RunningMode, Runtime},
+    trap::Trap,
+    watchdog, ExecError, RuntimeError,
 };
 
+// the same as DEFAULT_WASM_STACK_SIZE of WAMR
+const DEFAULT_STACK_SIZE: u32 = 16 * 1024;
+
+/// the largest limit `Instance::set_call_fuel_limit()` accepts, WAMR keeps it in an `i32`
+#[cfg(feature = "instruction-metering")]
+pub const MAX_CALL_FUEL_LIMIT: u64 = i32::MAX as u64;
+
 #[derive(Debug)]
 pub struct Instance<'module> {
     instance: wasm_module_inst_t,
-    _phantom: PhantomData<Module<'module>>,
+    // of execution environments created by the SDK
+    stack_size: u32,
+    // shared with `TerminateHandle`s
+    shared: Arc<SharedInstance>,
+    default_timeout: Option<Duration>,
+    coredump_dir: Option<PathBuf>,
+    #[cfg(feature = "instruction-metering")]
+    call_fuel_limit: Cell<Option<u64>>,
+    // of the last `proc_exit`, WAMR doesn't see the one of the SDK
+    wasi_exit_code: Cell<Option<u32>>,
+    module: &'module Module<'module>,
+}
+
+#[derive(Debug)]
+struct RawInstance(wasm_module_inst_t);
+
+// WAMR allows to terminate an instance from another thread
+unsafe impl Send for RawInstance {}
+
+#[derive(Debug)]
+struct SharedInstance {
+    // `None` after the instance is dropped
+    instance: Mutex<Option<RawInstance>>,
+    terminated: AtomicBool,
+    // terminated by the watchdog
+    timed_out: AtomicBool,
+}
+
+/// A handle to terminate the execution of an instance from another thread.
+/// Get one via `Instance::terminate_handle()`
+///
+/// It is safe to keep the handle after the instance is dropped, `terminate()`
+/// does nothing then.
+#[derive(Debug, Clone)]
+pub struct TerminateHandle {
+    shared: Arc<SharedInstance>,
+}
+
+impl TerminateHandle {
+    /// abort the function running in the instance, if any. The call will return
+    /// `RuntimeError::Terminated`
+    pub fn terminate(&self) {
+        let instance = self.shared.instance.lock().unwrap();
+        if let Some(instance) = instance.as_ref() {
+            self.shared.terminated.store(true, Ordering::SeqCst);
+            unsafe { wasm_runtime_terminate(instance.0) };
+        }
+    }
+
+    /// terminate because of a passed deadline
+    pub(crate) fn time_out(&self) {
+        self.shared.timed_out.store(true, Ordering::SeqCst);
+        self.terminate();
+    }
 }
 
 impl<'module> Instance<'module> {
@@ -48,10 +155,32 @@ impl<'module> Instance<'module> {
     ///
     /// Return `RuntimeError::CompilationError` if failed.
     pub fn new_with_args(
-        _runtime: &Runtime,
+        runtime: &Runtime,
         module: &'module Module<'module>,
         stack_size: u32,
         heap_size: u32,
+    ) -> Result<Self, RuntimeError> {
+        Self::builder(runtime, module)
+            .default_stack_size(stack_size)
+            .host_managed_heap_size(heap_size)
+            .build()
+    }
+
+    /// return an `InstanceBuilder` to instantiate a module with more configuration,
+    /// like limiting the linear memory
+    pub fn builder(
+        _runtime: &Runtime,
+        module: &'module Module<'module>,
+    ) -> InstanceBuilder<'module> {
+        InstanceBuilder {
+            module,
+            args: InstantiationArgs::default(),
+        }
+    }
+
+    fn instantiate(
+        module: &'module Module<'module>,
+        args: &InstantiationArgs,
     ) -> Result<Self, RuntimeError> {
         let init_thd_env = unsafe { wasm_runtime_init_thread_env() };
         if !init_thd_env {
@@ -62,10 +191,9 @@ impl<'module> Instance<'module> {
 
         let mut error_buf = [0 as c_char; DEFAULT_ERROR_BUF_SIZE];
         let instance = unsafe {
-            wasm_runtime_instantiate(
+            wasm_runtime_instantiate_ex(
                 module.get_inner_module(),
-                stack_size,
-                heap_size,
+                args,
                 error_buf.as_mut_ptr(),
                 error_buf.len() as u32,
             )
@@ -86,19 +214,497 @@ impl<'module> Instance<'module> {
             }
         }
 
+        let stack_size = match args.default_stack_size {
+            0 => DEFAULT_STACK_SIZE,
+            stack_size => stack_size,
+        };
+
         Ok(Instance {
             instance,
-            _phantom: PhantomData,
+            stack_size,
+            shared: Arc::new(SharedInstance {
+                instance: Mutex::new(Some(RawInstance(instance))),
+                terminated: AtomicBool::new(false),
+                timed_out: AtomicBool::new(false),
+            }),
+            default_timeout: None,
+            coredump_dir: None,
+            #[cfg(feature = "instruction-metering")]
+            call_fuel_limit: Cell::new(None),
+            wasi_exit_code: Cell::new(None),
+            module,
         })
     }
 
+    /// return a `Send + Sync` handle to terminate the running function from another thread
+    pub fn terminate_handle(&self) -> TerminateHandle {
+        TerminateHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// clear the termination state before a new call.
+    /// return whether the instance has been terminated since the last call
+    fn take_terminated(&self) -> bool {
+        self.shared.terminated.swap(false, Ordering::SeqCst)
+    }
+
+    /// clear the timeout state before a new call.
+    /// return whether the instance has timed out since the last call
+    fn take_timed_out(&self) -> bool {
+        self.shared.timed_out.swap(false, Ordering::SeqCst)
+    }
+
+    /// set the wall-clock time limit of every `Function::call()` on the instance.
+    /// `None`, the default, means no limit
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.default_timeout = timeout;
+    }
+
+    pub fn get_default_timeout(&self) -> Option<Duration> {
+        self.default_timeout
+    }
+
+    /// write a coredump into `dir` whenever a call traps. see `ExecError::coredump`.
+    /// `None`, the default, means no coredump. only exported globals are captured
+    pub fn set_coredump_dir(&mut self, dir: Option<PathBuf>) {
+        self.coredump_dir = dir;
+    }
+
+    pub fn get_coredump_dir(&self) -> Option<&Path> {
+        self.coredump_dir.as_deref()
+    }
+
+    /// the current and the maximum number of pages of the default linear memory.
+    /// `None` if there is no memory
+    pub fn get_memory_page_count(&self) -> Option<(u64, u64)> {
+        unsafe {
+            let memory = wasm_runtime_get_default_memory(self.instance);
+            if memory.is_null() {
+                return None;
+            }
+            Some((
+                wasm_memory_get_cur_page_count(memory),
+                wasm_memory_get_max_page_count(memory),
+            ))
+        }
+    }
+
+    /// a copy of the default linear memory. `None` if there is no memory
+    pub fn copy_memory(&self) -> Option<Vec<u8>> {
+        unsafe {
+            let memory = wasm_runtime_get_default_memory(self.instance);
+            if memory.is_null() {
+                return None;
+            }
+
+            let size =
+                wasm_memory_get_cur_page_count(memory) * wasm_memory_get_bytes_per_page(memory);
+            let base = wasm_memory_get_base_address(memory) as *const u8;
+            if base.is_null() || size == 0 {
+                return Some(Vec::new());
+            }
+            Some(slice::from_raw_parts(base, size as usize).to_vec())
+        }
+    }
+
+    /// run the functions of the instance in `mode`, instead of the default of the runtime.
+    /// an instance of an AOT module always runs AOT code
+    ///
+    /// # Errors
+    ///
+    /// if the mode isn't compiled in, it will return `RuntimeError::FeatureNotEnabled`.
+    /// if WAMR rejects it anyway, like for an AOT module, it will return
+    /// `RuntimeError::InvalidConfiguration`
+    pub fn set_running_mode(&mut self, mode: RunningMode) -> Result<(), RuntimeError> {
+        if !mode.is_compiled_in() {
+            return Err(RuntimeError::FeatureNotEnabled(mode.feature()));
+        }
+
+        match unsafe { wasm_runtime_set_running_mode(self.instance, mode.into()) } {
+            true => Ok(()),
+            false => Err(RuntimeError::InvalidConfiguration(format!(
+                "the instance can't run in {:?}",
+                mode
+            ))),
+        }
+    }
+
+    /// the mode the functions of the instance run in. `None` for an instance of an
+    /// AOT module, which runs AOT code
+    pub fn running_mode(&self) -> Option<RunningMode> {
+        let mode = unsafe { wasm_runtime_get_running_mode(self.instance) };
+        RunningMode::from_raw(mode)
+    }
+
+    /// set the number of wasm instructions every call of the instance is allowed to
+    /// execute. each call starts with the full limit, unused fuel isn't carried over,
+    /// since WAMR doesn't report how many instructions a call executes.
+    ///
+    /// Unlike timeouts, the same limit stops the same code at the same place.
+    /// A call beyond it returns `RuntimeError::OutOfFuel`.
+    ///
+    /// if `limit` is above `MAX_CALL_FUEL_LIMIT`, it will return
+    /// `RuntimeError::InvalidConfiguration`
+    #[cfg(feature = "instruction-metering")]
+    pub fn set_call_fuel_limit(&mut self, limit: u64) -> Result<(), RuntimeError> {
+        if limit > MAX_CALL_FUEL_LIMIT {
+            return Err(RuntimeError::InvalidConfiguration(format!(
+                "call fuel limit {} is above {}",
+                limit, MAX_CALL_FUEL_LIMIT
+            )));
+        }
+        self.call_fuel_limit.set(Some(limit));
+        Ok(())
+    }
+
+    /// the limit given by `set_call_fuel_limit()`, `None` if it is never called
+    #[cfg(feature = "instruction-metering")]
+    pub fn get_call_fuel_limit(&self) -> Option<u64> {
+        self.call_fuel_limit.get()
+    }
+
+    // the limit goes to every execution environment a call runs in, the singleton
+    // one or the one `run_wasi_main()` creates
+    #[cfg(feature = "instruction-metering")]
+    fn apply_call_fuel_limit(&self, exec_env: wasm_exec_env_t) {
+        if let Some(limit) = self.call_fuel_limit.get() {
+            // set_call_fuel_limit() keeps it in range, -1 would mean no limit for WAMR
+            unsafe { wasm_runtime_set_instruction_count_limit(exec_env, limit as i32) };
+        }
+    }
+
+    /// the exit code passed to `proc_exit` by a WASI program. 0 if the instance isn't
+    /// in WASI mode or `proc_exit` is never called
+    pub fn get_wasi_exit_code(&self) -> u32 {
+        if let Some(exit_code) = self.wasi_exit_code.get() {
+            return exit_code;
+        }
+
+        unsafe {
+            if wasm_runtime_is_wasi_mode(self.instance) {
+                wasm_runtime_get_wasi_exit_code(self.instance)
+            } else {
+                0
+            }
+        }
+    }
+
+    /// run `_start` of a WASI command module in a new execution environment.
+    /// arguments and environment variables come from the `WasiCtx` of the module.
+    ///
+    /// the default timeout and the call fuel limit of the instance, if any, apply.
+    ///
+    /// # Error
+    ///
+    /// Return the exit code, which is the one passed to `proc_exit` or 0 if `_start` returns.
+    /// Return `RuntimeError::FunctionNotFound` if there is no `_start`.
+    /// Others are the same as `Function::call()`
+    pub fn run_wasi_main(&self) -> Result<u32, RuntimeError> {
+        let start_function = unsafe { wasm_runtime_lookup_wasi_start_function(self.instance) };
+        if start_function.is_null() {
+            return Err(RuntimeError::FunctionNotFound);
+        }
+
+        let exec_env = unsafe { wasm_runtime_create_exec_env(self.instance, self.stack_size) };
+        if exec_env.is_null() {
+            return Err(RuntimeError::ExecutionError(ExecError {
+                message: String::from("failed to create an execution environment"),
+                exit_code: 0xff,
+                trap: Trap::OutOfMemory,
+                frames: Vec::new(),
+                coredump: None,
+            }));
+        }
+
+        let result = self.execute(self.default_timeout, exec_env, || unsafe {
+            wasm_runtime_call_wasm(exec_env, start_function, 0, ptr::null_mut())
+        });
+        unsafe { wasm_runtime_destroy_exec_env(exec_env) };
+
+        self.exit_code_of(result)
+    }
+
+    /// run the `main` of a module with `args`, like `wasm_application_execute_main()`.
+    ///
+    /// `args` only reach a non-WASI `main(argc, argv)`. WAMR runs `_start` of a WASI
+    /// command module instead, with arguments from the `WasiCtx` of the module.
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::InvalidConfiguration` if an argument contains a NUL.
+    /// Others are the same as `run_wasi_main()`
+    pub fn run_main(&self, args: &[&str]) -> Result<u32, RuntimeError> {
+        let args = args
+            .iter()
+            .map(|arg| {
+                CString::new(*arg).map_err(|_| {
+                    RuntimeError::InvalidConfiguration(format!("argument {:?} contains a NUL", arg))
+                })
+            })
+            .collect::<Result<Vec<CString>, RuntimeError>>()?;
+        let mut argv = args
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut c_char)
+            .collect::<Vec<*mut c_char>>();
+
+        // `wasm_application_execute_main()` runs in the singleton execution environment
+        let exec_env = unsafe { wasm_runtime_get_exec_env_singleton(self.instance) };
+        let result = self.execute(self.default_timeout, exec_env, || unsafe {
+            wasm_application_execute_main(self.instance, argv.len() as i32, argv.as_mut_ptr())
+        });
+
+        self.exit_code_of(result)
+    }
+
+    fn exit_code_of(&self, result: Result<(), RuntimeError>) -> Result<u32, RuntimeError> {
+        match result {
+            // `proc_exit` is reported as `RuntimeError::Exit`, even with 0
+            Ok(()) => Ok(0),
+            Err(RuntimeError::Exit(exit_code)) => Ok(exit_code),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// run `call` in `exec_env` within `timeout`. `call` returns whether the execution
+    /// succeeds. turn a failure into the matching `RuntimeError`, `RuntimeError::Exit`
+    /// with any code for `proc_exit`
+    pub(crate) fn execute<F>(
+        &self,
+        timeout: Option<Duration>,
+        exec_env: wasm_exec_env_t,
+        call: F,
+    ) -> Result<(), RuntimeError>
+    where
+        F: FnOnce() -> bool,
+    {
+        // a termination of the previous call shouldn't affect this one. clear the
+        // exception first, a termination from now on has to stop this call
+        unsafe { wasm_runtime_clear_exception(self.instance) };
+        self.take_terminated();
+        self.take_timed_out();
+        proc_exit::take(self.instance);
+
+        #[cfg(feature = "instruction-metering")]
+        self.apply_call_fuel_limit(exec_env);
+
+        let _deadline =
+            timeout.map(|timeout| watchdog::arm(Instant::now() + timeout, self.terminate_handle()));
+
+        let succeeded = call();
+
+        // WAMR reports a success after `proc_exit`, whatever the exit code is
+        if let Some(exit_code) = proc_exit::take(self.instance) {
+            self.wasi_exit_code.set(Some(exit_code));
+            return Err(RuntimeError::Exit(exit_code));
+        }
+        if succeeded {
+            return Ok(());
+        }
+
+        // the watchdog terminates the instance as well
+        let terminated = self.take_terminated();
+        if self.take_timed_out() {
+            return Err(RuntimeError::Timeout);
+        }
+        if terminated {
+            return Err(RuntimeError::Terminated);
+        }
+
+        let message = exception_to_string(unsafe { wasm_runtime_get_exception(self.instance) });
+        let mut error_info = ExecError {
+            trap: Trap::from_exception(&message),
+            message,
+            exit_code: self.get_wasi_exit_code(),
+            frames: self.call_stack(exec_env),
+            coredump: None,
+        };
+
+        match error_info.trap {
+            Trap::Exit => Err(RuntimeError::Exit(error_info.exit_code)),
+            // terminated after the exception is cleared but before the flag is reset
+            Trap::Terminated => Err(RuntimeError::Terminated),
+            #[cfg(feature = "instruction-metering")]
+            Trap::OutOfFuel => Err(RuntimeError::OutOfFuel),
+            _ => {
+                error_info.coredump = self.dump_core(&error_info.frames);
+                Err(RuntimeError::ExecutionError(error_info))
+            }
+        }
+    }
+
+    // a failure to write the coredump shouldn't hide the trap
+    fn dump_core(&self, frames: &[Frame]) -> Option<PathBuf> {
+        let dir = self.coredump_dir.as_ref()?;
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(coredump_file_name(self.module.get_name(), timestamp_ms));
+        write_coredump(self, frames, &path).ok().map(|_| path)
+    }
+
+    #[cfg(feature = "dump-call-stack")]
+    fn call_stack(&self, exec_env: wasm_exec_env_t) -> Vec<Frame> {
+        let size = unsafe { wasm_runtime_get_call_stack_buf_size(exec_env) };
+        if size == 0 {
+            return Vec::new();
+        }
+
+        // one more for the NUL
+        let mut buf = vec![0 as c_char; size as usize + 1];
+        unsafe { wasm_runtime_dump_call_stack_to_buf(exec_env, buf.as_mut_ptr(), size) };
+
+        #[allow(unused_mut)]
+        let mut frames = parse_call_stack(
+            &cstr_to_string(buf.as_ptr()),
+            self.module.get_name(),
+            self.module.get_function_names(),
+        );
+
+        #[cfg(feature = "dwarf")]
+        crate::dwarf::symbolize(self.module.get_content(), &mut frames);
+
+        frames
+    }
+
+    /// per-function call counts and execution time since the instance is instantiated.
+    /// functions are named by the name section or exports
+    #[cfg(feature = "perf-profiling")]
+    pub fn profile(&self) -> Profile {
+        let total_time = millis(unsafe { wasm_runtime_sum_wasm_exec_time(self.instance) });
+        let dump = log_hook::capture(|| unsafe { wasm_runtime_dump_perf_profiling(self.instance) });
+
+        Profile {
+            total_time,
+            functions: parse_perf_profiling(&dump, self.module.get_function_names()),
+        }
+    }
+
+    /// the execution time of a function, including the functions it calls.
+    /// `name` is from the name section or the export
+    #[cfg(feature = "perf-profiling")]
+    pub fn get_function_exec_time(&self, name: &str) -> Option<Duration> {
+        let name = CString::new(name).ok()?;
+        let ms = unsafe { wasm_runtime_get_wasm_func_exec_time(self.instance, name.as_ptr()) };
+        // -1 if not found
+        match ms < 0.0 {
+            true => None,
+            false => Some(millis(ms)),
+        }
+    }
+
+    #[cfg(not(feature = "dump-call-stack"))]
+    fn call_stack(&self, _exec_env: wasm_exec_env_t) -> Vec<Frame> {
+        Vec::new()
+    }
+
+    /// the memory consumed by the instance, its module and its default execution
+    /// environment, for accounting
+    #[cfg(feature = "memory-profiling")]
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let dump = log_hook::capture(|| unsafe {
+            let exec_env = wasm_runtime_get_exec_env_singleton(self.instance);
+            wasm_runtime_dump_mem_consumption(exec_env)
+        });
+        parse_memory_usage(&dump)
+    }
+
+    /// start a debug server for the instance and return its port. connect to it
+    /// with the lldb of WAMR, `process connect -p wasm connect://<ip>:<port>`.
+    ///
+    /// the function called next waits for the debugger before it runs.
+    /// configure the address via `RuntimeBuilder::debug_server()`
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::DebugServerFailure` if failed.
+    #[cfg(feature = "debug-interp")]
+    pub fn start_debug_server(&self) -> Result<u32, RuntimeError> {
+        let port = unsafe {
+            let exec_env = wasm_runtime_get_exec_env_singleton(self.instance);
+            wasm_runtime_start_debug_instance(exec_env)
+        };
+        match port {
+            0 => Err(RuntimeError::DebugServerFailure),
+            port => Ok(port),
+        }
+    }
+
+    pub fn get_module(&self) -> &'module Module<'module> {
+        self.module
+    }
+
     pub fn get_inner_instance(&self) -> wasm_module_inst_t {
         self.instance
     }
 }
 
+/// The builder of `Instance`. It is used to configure the instantiation.
+/// Get one via `Instance::builder()`
+///
+/// It is backed by `InstantiationArgs`. `InstantiationArgs2` of newer WAMR adds only
+/// WASI settings on top of it: argv, environment, directories, stdio and address pools.
+/// They aren't exposed here, WASI is configured per module by `Module::set_wasi_context()`
+/// and two sources of WASI settings for one instance would contradict each other.
+pub struct InstanceBuilder<'module> {
+    module: &'module Module<'module>,
+    args: InstantiationArgs,
+}
+
+impl<'module> InstanceBuilder<'module> {
+    /// the size of the wasm operand stack (interpreter) or the native stack (AOT)
+    /// of the default execution environment
+    pub fn default_stack_size(mut self, stack_size: u32) -> InstanceBuilder<'module> {
+        self.args.default_stack_size = stack_size;
+        self
+    }
+
+    /// the size of the app heap managed by the host, for `-nostdlib` Wasm and wasm32-unknown
+    pub fn host_managed_heap_size(mut self, heap_size: u32) -> InstanceBuilder<'module> {
+        self.args.host_managed_heap_size = heap_size;
+        self
+    }
+
+    /// the maximum number of 64KiB pages of the default linear memory.
+    /// It takes effect if it is less than what the module declares. 0 means no extra limit
+    pub fn max_memory_pages(mut self, pages: u32) -> InstanceBuilder<'module> {
+        self.args.max_memory_pages = pages;
+        self
+    }
+
+    /// instantiate the module with the configuration
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::InstantiationFailure` if failed.
+    pub fn build(self) -> Result<Instance<'module>, RuntimeError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "wamr.instantiate",
+            module = self.module.get_name(),
+            duration_us = tracing::field::Empty,
+            error = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let result = Instance::instantiate(self.module, &self.args);
+
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&span, start, &result);
+        result
+    }
+}
+
 impl Drop for Instance<'_> {
     fn drop(&mut self) {
+        // no more termination after this point
+        self.shared.instance.lock().unwrap().take();
+        // the address may be taken by another instance
+        proc_exit::take(self.instance);
+
         unsafe {
             wasm_runtime_destroy_thread_env();
             wasm_runtime_deinstantiate(self.instance);
@@ -109,10 +715,10 @@ impl Drop for Instance<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::runtime::Runtime;
-    use wamr_sys::{
-        wasm_runtime_get_running_mode, RunningMode_Mode_Interp, RunningMode_Mode_LLVM_JIT,
+    use crate::{
+        function::Function, runtime::Runtime, value::WasmValue, wasi_context::WasiCtxBuilder,
     };
+    use std::path::PathBuf;
 
     #[test]
     fn test_instance_new() {
@@ -145,13 +751,244 @@ mod tests {
 
         let instance = instance.unwrap();
         assert_eq!(
-            unsafe { wasm_runtime_get_running_mode(instance.get_inner_instance()) },
-            if cfg!(feature = "llvmjit") {
-                RunningMode_Mode_LLVM_JIT
-            } else {
-                RunningMode_Mode_Interp
-            }
+            instance.running_mode(),
+            Some(runtime.capabilities().default_running_mode)
+        );
+    }
+
+    #[test]
+    fn test_instance_builder() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (memory (export "memory") 1)
+        //   (func (export "grow") (param i32) (result i32)
+        //     (memory.grow (local.get 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x01, 0x7f,
+            0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x11, 0x02,
+            0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x04, 0x67, 0x72, 0x6f, 0x77,
+            0x00, 0x00, 0x0a, 0x08, 0x01, 0x06, 0x00, 0x20, 0x00, 0x40, 0x00, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+
+        let module = Module::from_vec(&runtime, binary, "grow");
+        assert!(module.is_ok());
+        let module = &module.unwrap();
+
+        let instance = Instance::builder(&runtime, module)
+            .default_stack_size(1024)
+            .host_managed_heap_size(0)
+            .max_memory_pages(2)
+            .build();
+        assert!(instance.is_ok());
+        let instance = &instance.unwrap();
+
+        let function = Function::find_export_func(instance, "grow").unwrap();
+
+        // 1 -> 2 pages
+        let result = function.call(instance, &vec![WasmValue::I32(1)]);
+        assert_eq!(result.unwrap(), vec![WasmValue::I32(1)]);
+
+        // beyond max_memory_pages
+        let result = function.call(instance, &vec![WasmValue::I32(1)]);
+        assert_eq!(result.unwrap(), vec![WasmValue::I32(-1)]);
+    }
+
+    #[test]
+    fn test_instance_coredump_dir() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (memory 1 1)
+        //   (func (export "crash") unreachable)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x04, 0x01, 0x01, 0x01, 0x01, 0x07, 0x09, 0x01, 0x05,
+            0x63, 0x72, 0x61, 0x73, 0x68, 0x00, 0x00, 0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "crash").unwrap();
+        let mut instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert_eq!(instance.get_memory_page_count(), Some((1, 1)));
+        assert_eq!(
+            instance.copy_memory().map(|memory| memory.len()),
+            Some(65536)
         );
+
+        let dir = std::env::temp_dir();
+        instance.set_coredump_dir(Some(dir.clone()));
+        let instance = &instance;
+        let function = Function::find_export_func(instance, "crash").unwrap();
+
+        let coredump = match function.call(instance, &vec![]) {
+            Err(RuntimeError::ExecutionError(error)) => error.coredump.unwrap(),
+            other => panic!("expect a trap, got {:?}", other),
+        };
+        assert!(coredump.starts_with(&dir));
+        assert!(std::fs::read(&coredump).unwrap().starts_with(b"\0asm"));
+        std::fs::remove_file(coredump).unwrap();
+    }
+
+    #[test]
+    fn test_instance_run_wasi_main() {
+        let runtime = Runtime::new().unwrap();
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("wasi-demo-app.wasm");
+        let mut module = Module::from_file(&runtime, d.as_path()).unwrap();
+
+        let wasi_ctx = WasiCtxBuilder::new()
+            .set_arguments(vec!["wasi-demo-app.wasm", "echo", "hi"])
+            .build();
+        module.set_wasi_context(wasi_ctx);
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert_eq!(instance.run_wasi_main().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_instance_run_wasi_main_proc_exit() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        //   (memory (export "memory") 1)
+        //   (func (export "_start")
+        //     (call $proc_exit (i32.const 7))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x60, 0x01, 0x7f,
+            0x00, 0x60, 0x00, 0x00, 0x02, 0x24, 0x01, 0x16, 0x77, 0x61, 0x73, 0x69, 0x5f, 0x73,
+            0x6e, 0x61, 0x70, 0x73, 0x68, 0x6f, 0x74, 0x5f, 0x70, 0x72, 0x65, 0x76, 0x69, 0x65,
+            0x77, 0x31, 0x09, 0x70, 0x72, 0x6f, 0x63, 0x5f, 0x65, 0x78, 0x69, 0x74, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x01, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x13, 0x02, 0x06, 0x6d,
+            0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x06, 0x5f, 0x73, 0x74, 0x61, 0x72, 0x74,
+            0x00, 0x01, 0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x07, 0x10, 0x00, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+        let mut module = Module::from_vec(&runtime, binary, "proc_exit").unwrap();
+        module.set_wasi_context(WasiCtxBuilder::new().build());
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert_eq!(instance.run_wasi_main().unwrap(), 7);
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert_eq!(instance.run_main(&[]).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_instance_run_main_nul_argument() {
+        let runtime = Runtime::new().unwrap();
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("wasi-demo-app.wasm");
+        let mut module = Module::from_file(&runtime, d.as_path()).unwrap();
+        module.set_wasi_context(WasiCtxBuilder::new().build());
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert!(matches!(
+            instance.run_main(&["echo", "h\0i"]),
+            Err(RuntimeError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "instruction-metering")]
+    fn test_instance_run_wasi_main_out_of_fuel() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (memory (export "memory") 1)
+        //   (func (export "_start")
+        //     (loop (br 0))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x13, 0x02, 0x06, 0x6d,
+            0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x06, 0x5f, 0x73, 0x74, 0x61, 0x72, 0x74,
+            0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x0b,
+        ];
+        let binary = binary.into_iter().map(|c| c as u8).collect::<Vec<u8>>();
+        let mut module = Module::from_vec(&runtime, binary, "spin").unwrap();
+        module.set_wasi_context(WasiCtxBuilder::new().build());
+
+        let mut instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        instance.set_call_fuel_limit(10_000).unwrap();
+        assert!(matches!(
+            instance.run_wasi_main(),
+            Err(RuntimeError::OutOfFuel)
+        ));
+        assert_eq!(instance.get_call_fuel_limit(), Some(10_000));
+    }
+
+    #[test]
+    #[cfg(feature = "memory-profiling")]
+    fn test_instance_memory_usage() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (memory 1 1)
+        //   (func (export "crash") unreachable)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            0x03, 0x02, 0x01, 0x00, 0x05, 0x04, 0x01, 0x01, 0x01, 0x01, 0x07, 0x09, 0x01, 0x05,
+            0x63, 0x72, 0x61, 0x73, 0x68, 0x00, 0x00, 0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "crash").unwrap();
+        let module_usage = module.memory_usage();
+        assert!(module_usage.total > 0);
+
+        let instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        let usage = instance.memory_usage();
+        assert_eq!(usage.module, module_usage);
+        assert!(usage.instance.memories >= 65536);
+        assert_eq!(usage.exec_env.stack, 1024 * 64);
+        assert!(usage.total >= usage.module.total + usage.instance.total);
+    }
+
+    #[test]
+    #[cfg(feature = "perf-profiling")]
+    fn test_instance_profile() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "add") (param i32 i32) (result i32)
+        //     (local.get 0)
+        //     (local.get 1)
+        //     (i32.add)
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f,
+            0x7f, 0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+            0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "add").unwrap();
+        let instance = &Instance::new(&runtime, &module, 1024 * 64).unwrap();
+        assert!(instance.profile().functions.is_empty());
+
+        let function = Function::find_export_func(instance, "add").unwrap();
+        for _ in 0..3 {
+            function
+                .call(instance, &vec![WasmValue::I32(1), WasmValue::I32(2)])
+                .unwrap();
+        }
+
+        let profile = instance.profile();
+        let add = profile.get_function("add").unwrap();
+        assert_eq!(add.func_index, Some(0));
+        assert_eq!(add.call_count, 3);
+        assert!(add.self_time <= add.total_time);
+        assert!(instance.get_function_exec_time("add").is_some());
+        assert!(instance.get_function_exec_time("sub").is_none());
     }
 
     #[test]
@@ -183,12 +1020,8 @@ mod tests {
 
         let instance = instance.unwrap();
         assert_eq!(
-            unsafe { wasm_runtime_get_running_mode(instance.get_inner_instance()) },
-            if cfg!(feature = "llvmjit") {
-                RunningMode_Mode_LLVM_JIT
-            } else {
-                RunningMode_Mode_Interp
-            }
+            instance.running_mode(),
+            Some(runtime.capabilities().default_running_mode)
         );
     }
 
@@ -224,9 +1057,49 @@ mod tests {
         assert!(instance.is_ok());
 
         let instance = instance.unwrap();
-        assert_eq!(
-            unsafe { wasm_runtime_get_running_mode(instance.get_inner_instance()) },
-            RunningMode_Mode_Interp
-        );
+        assert_eq!(instance.running_mode(), Some(RunningMode::Interp));
+    }
+
+    #[test]
+    fn test_instance_set_running_mode() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func (export "add") (param i32 i32) (result i32)
+        //     (local.get 0)
+        //     (local.get 1)
+        //     (i32.add)
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7f,
+            0x7f, 0x01, 0x7f, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+            0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+        ];
+        let module = Module::from_vec(&runtime, binary, "add").unwrap();
+        let mut instance = Instance::new(&runtime, &module, 1024 * 64).unwrap();
+
+        for mode in [
+            RunningMode::Interp,
+            RunningMode::FastJit,
+            RunningMode::LlvmJit,
+            RunningMode::MultiTierJit,
+        ] {
+            if !runtime.is_running_mode_supported(mode) {
+                assert!(matches!(
+                    instance.set_running_mode(mode),
+                    Err(RuntimeError::FeatureNotEnabled(_))
+                ));
+                continue;
+            }
+
+            assert!(instance.set_running_mode(mode).is_ok());
+            assert_eq!(instance.running_mode(), Some(mode));
+
+            let function = Function::find_export_func(&instance, "add").unwrap();
+            let params = vec![WasmValue::I32(3), WasmValue::I32(4)];
+            let result = function.call(&instance, &params);
+            assert_eq!(result.unwrap(), vec![WasmValue::I32(7)]);
+        }
     }
 }
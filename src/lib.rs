@@ -146,19 +146,50 @@ use std::fmt;
 use std::io;
 pub use wamr_sys as sys;
 
+mod allocator;
+#[cfg(feature = "wamrc")]
+pub mod aot;
+pub mod backtrace;
+mod binary;
+pub mod coredump;
+#[cfg(feature = "dwarf")]
+mod dwarf;
 pub mod function;
 mod helper;
 pub mod host_function;
 pub mod instance;
+#[cfg(feature = "log-hook")]
+mod log_hook;
+#[cfg(feature = "memory-profiling")]
+pub mod memory_usage;
 pub mod module;
+pub mod policy;
+mod proc_exit;
+#[cfg(feature = "perf-profiling")]
+pub mod profile;
 pub mod runtime;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod trap;
+pub mod types;
 pub mod value;
 pub mod wasi_context;
+mod watchdog;
 
+/// the details of a trap. more fields may come, so it can't be built outside the crate
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct ExecError {
+    /// the raw exception message of WAMR
     pub message: String,
     pub exit_code: u32,
+    /// the kind of the trap, classified from `message`
+    pub trap: trap::Trap,
+    /// the wasm call stack at the trap, the innermost first.
+    /// empty without the `dump-call-stack` feature
+    pub frames: Vec<backtrace::Frame>,
+    /// where the coredump is written, see `Instance::set_coredump_dir()`
+    pub coredump: Option<std::path::PathBuf>,
 }
 
 /// all kinds of exceptions raised by WAMR
@@ -177,6 +208,24 @@ pub enum RuntimeError {
     ExecutionError(ExecError),
     /// usually returns by `find_export_func()`
     FunctionNotFound,
+    /// a module is rejected by the `ModulePolicy` while loading
+    PolicyViolation(String),
+    /// the execution is aborted by `TerminateHandle::terminate()`
+    Terminated,
+    /// the execution runs beyond the time limit
+    Timeout,
+    /// the execution runs beyond the instruction limit set by `Instance::set_call_fuel_limit()`
+    OutOfFuel,
+    /// a WASI program calls `proc_exit` with a non-zero exit code
+    Exit(u32),
+    /// the debug server can't start
+    DebugServerFailure,
+    /// `RuntimeBuilder::build()` while the runtime is alive with another configuration
+    ConflictingConfiguration(String),
+    /// a `RuntimeBuilder` setting needs a cargo feature which isn't enabled
+    FeatureNotEnabled(&'static str),
+    /// a setting, like a `RuntimeBuilder` one or the fuel, is out of range or rejected
+    InvalidConfiguration(String),
 }
 
 impl fmt::Display for RuntimeError {
@@ -187,12 +236,30 @@ impl fmt::Display for RuntimeError {
             RuntimeError::WasmFileFSError(e) => write!(f, "Wasm file operation error: {}", e),
             RuntimeError::CompilationError(e) => write!(f, "Wasm compilation error: {}", e),
             RuntimeError::InstantiationFailure(e) => write!(f, "Wasm instantiation failure: {}", e),
-            RuntimeError::ExecutionError(info) => write!(
-                f,
-                "Wasm execution error: {} and {}",
-                info.message, info.exit_code
-            ),
+            RuntimeError::ExecutionError(info) => {
+                write!(
+                    f,
+                    "Wasm execution error: {} and {}",
+                    info.message, info.exit_code
+                )?;
+                backtrace::write_frames(f, &info.frames)
+            }
             RuntimeError::FunctionNotFound => write!(f, "Function not found"),
+            RuntimeError::PolicyViolation(e) => write!(f, "Module policy violation: {}", e),
+            RuntimeError::Terminated => write!(f, "Wasm execution terminated"),
+            RuntimeError::Timeout => write!(f, "Wasm execution timed out"),
+            RuntimeError::OutOfFuel => write!(f, "Wasm execution ran out of fuel"),
+            RuntimeError::Exit(code) => write!(f, "Wasm program exited with code {}", code),
+            RuntimeError::DebugServerFailure => write!(f, "Debug server start failure"),
+            RuntimeError::ConflictingConfiguration(e) => {
+                write!(f, "Conflicting runtime configuration: {}", e)
+            }
+            RuntimeError::FeatureNotEnabled(feature) => {
+                write!(f, "Feature not enabled: {}", feature)
+            }
+            RuntimeError::InvalidConfiguration(e) => {
+                write!(f, "Invalid runtime configuration: {}", e)
+            }
         }
     }
 }
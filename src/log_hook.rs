@@ -0,0 +1,241 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! forward what WAMR prints to Rust, by the vprintf and log callbacks of wamr-sys.
+//! with the `log` feature, every log and every other line becomes a `log` record of
+//! the target "wamr". otherwise, they are printed to stdout as WAMR does.
+//! the SDK captures what some dump APIs print, see `capture()`
+
+#[cfg(not(feature = "log"))]
+use std::io::{self, Write};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr},
+    slice,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Once,
+    },
+};
+
+use wamr_sys::{
+    log_level_t, log_level_t_WASM_LOG_LEVEL_WARNING, wamr_sys_set_log_record_writer,
+    wamr_sys_set_log_writer,
+};
+#[cfg(feature = "log")]
+use wamr_sys::{
+    log_level_t_WASM_LOG_LEVEL_DEBUG, log_level_t_WASM_LOG_LEVEL_ERROR,
+    log_level_t_WASM_LOG_LEVEL_FATAL,
+};
+
+/// the target of `log` records
+#[cfg(feature = "log")]
+pub(crate) const LOG_TARGET: &str = "wamr";
+
+// WAMR doesn't filter logs passed to the log callback. the same default as WAMR
+static LEVEL: AtomicU32 = AtomicU32::new(log_level_t_WASM_LOG_LEVEL_WARNING);
+
+// WAMR prints a line in pieces. keep them until the line ends
+struct PendingLine(RefCell<Vec<u8>>);
+
+impl Drop for PendingLine {
+    // the thread ends
+    fn drop(&mut self) {
+        flush(&mut self.0.borrow_mut());
+    }
+}
+
+thread_local! {
+    static PENDING_LINE: PendingLine = const { PendingLine(RefCell::new(Vec::new())) };
+    // what is printed during `capture()`
+    static CAPTURED: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// install the writers before WAMR prints anything
+pub(crate) fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        wamr_sys_set_log_writer(Some(write_log));
+        wamr_sys_set_log_record_writer(Some(write_record));
+    });
+}
+
+/// the most verbose level to forward, see `Runtime::set_log_level()`
+pub(crate) fn set_level(level: log_level_t) {
+    LEVEL.store(level, Ordering::Relaxed);
+}
+
+extern "C" fn write_log(message: *const c_char, len: c_int) {
+    if message.is_null() || len <= 0 {
+        return;
+    }
+
+    let message = unsafe { slice::from_raw_parts(message as *const u8, len as usize) };
+    // never unwind into C
+    let captured = CAPTURED
+        .try_with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => {
+                captured.extend_from_slice(message);
+                true
+            }
+            None => false,
+        })
+        .unwrap_or(false);
+    if captured {
+        return;
+    }
+
+    let _ = PENDING_LINE.try_with(|pending| {
+        let mut pending = pending.0.borrow_mut();
+        pending.extend_from_slice(message);
+
+        while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            emit(String::from_utf8_lossy(&line[..end]).trim_end());
+        }
+    });
+}
+
+extern "C" fn write_record(
+    level: u32,
+    file: *const c_char,
+    line: c_int,
+    message: *const c_char,
+    len: c_int,
+) {
+    if level > LEVEL.load(Ordering::Relaxed) || message.is_null() || len < 0 {
+        return;
+    }
+
+    let message = unsafe { slice::from_raw_parts(message as *const u8, len as usize) };
+    let message = String::from_utf8_lossy(message);
+    let file = match file.is_null() {
+        true => Default::default(),
+        false => unsafe { CStr::from_ptr(file) }.to_string_lossy(),
+    };
+    emit_record(level, &file, line, message.trim_end());
+}
+
+// a line without the newline yet
+fn flush(pending: &mut Vec<u8>) {
+    if !pending.is_empty() {
+        let line: Vec<u8> = pending.drain(..).collect();
+        emit(String::from_utf8_lossy(&line).trim_end());
+    }
+}
+
+fn flush_pending() {
+    let _ = PENDING_LINE.try_with(|pending| flush(&mut pending.0.borrow_mut()));
+}
+
+// ends `capture()` even if it panics, which would swallow prints of the thread forever
+struct Capturing;
+
+impl Drop for Capturing {
+    fn drop(&mut self) {
+        let _ = CAPTURED.try_with(|captured| captured.take());
+    }
+}
+
+/// run `f` and return what WAMR prints on this thread meanwhile, instead of logging it.
+/// it is empty if `WAMR_BH_VPRINTF` replaces the callback of wamr-sys
+#[cfg_attr(
+    not(any(feature = "memory-profiling", feature = "perf-profiling")),
+    allow(dead_code)
+)]
+pub(crate) fn capture<F: FnOnce()>(f: F) -> String {
+    // a partial line before belongs to neither
+    flush_pending();
+    CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let capturing = Capturing;
+    f();
+    let captured = CAPTURED
+        .with(|captured| captured.take())
+        .unwrap_or_default();
+    drop(capturing);
+    String::from_utf8_lossy(&captured).into_owned()
+}
+
+#[cfg(feature = "log")]
+fn emit(line: &str) {
+    log::log!(target: LOG_TARGET, log::Level::Info, "{}", line);
+}
+
+#[cfg(not(feature = "log"))]
+fn emit(line: &str) {
+    let _ = writeln!(io::stdout(), "{}", line);
+}
+
+#[cfg(feature = "log")]
+fn emit_record(level: u32, file: &str, line: c_int, message: &str) {
+    let level = level_of(level);
+    if level > log::max_level() {
+        return;
+    }
+
+    // only debug logs carry the location
+    log::logger().log(
+        &log::Record::builder()
+            .target(LOG_TARGET)
+            .level(level)
+            .file(Some(file).filter(|file| !file.is_empty()))
+            .line(u32::try_from(line).ok().filter(|line| *line > 0))
+            .args(format_args!("{}", message))
+            .build(),
+    );
+}
+
+// like bh_log(), with the location if any
+#[cfg(not(feature = "log"))]
+fn emit_record(_level: u32, file: &str, line: c_int, message: &str) {
+    let _ = match file.is_empty() {
+        true => writeln!(io::stdout(), "{}", message),
+        false => writeln!(io::stdout(), "{}, line {}, {}", file, line, message),
+    };
+}
+
+/// the level of `log` for a `log_level_t` of WAMR
+#[cfg(feature = "log")]
+fn level_of(level: u32) -> log::Level {
+    #[allow(non_upper_case_globals)]
+    match level {
+        log_level_t_WASM_LOG_LEVEL_FATAL | log_level_t_WASM_LOG_LEVEL_ERROR => log::Level::Error,
+        log_level_t_WASM_LOG_LEVEL_WARNING => log::Level::Warn,
+        log_level_t_WASM_LOG_LEVEL_DEBUG => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use super::*;
+    use wamr_sys::log_level_t_WASM_LOG_LEVEL_VERBOSE;
+
+    #[test]
+    fn test_level_of() {
+        let cases = [
+            (log_level_t_WASM_LOG_LEVEL_FATAL, log::Level::Error),
+            (log_level_t_WASM_LOG_LEVEL_ERROR, log::Level::Error),
+            (log_level_t_WASM_LOG_LEVEL_WARNING, log::Level::Warn),
+            (log_level_t_WASM_LOG_LEVEL_DEBUG, log::Level::Debug),
+            (log_level_t_WASM_LOG_LEVEL_VERBOSE, log::Level::Trace),
+        ];
+
+        for (level, expected) in cases {
+            assert_eq!(level_of(level), expected, "{}", level);
+        }
+    }
+
+    #[test]
+    fn test_capture_panic() {
+        let text = b"captured\n";
+        let captured = capture(|| write_log(text.as_ptr() as *const c_char, text.len() as c_int));
+        assert_eq!(captured, "captured\n");
+
+        let panicked = std::panic::catch_unwind(|| capture(|| panic!("in capture")));
+        assert!(panicked.is_err());
+        assert!(CAPTURED.with(|captured| captured.borrow().is_none()));
+    }
+}
@@ -0,0 +1,248 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! memory consumed by the runtime for a module and an instance, in bytes,
+//! with the `memory-profiling` feature.
+//! get one via `Module::memory_usage()` or `Instance::memory_usage()`
+
+use std::collections::BTreeMap;
+
+/// The memory consumed by a loaded module
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleMemoryUsage {
+    pub total: u64,
+    pub module_struct: u64,
+    pub types: u64,
+    pub imports: u64,
+    pub functions: u64,
+    pub tables: u64,
+    pub memories: u64,
+    pub globals: u64,
+    pub exports: u64,
+    pub table_segments: u64,
+    pub data_segments: u64,
+    pub const_strings: u64,
+    /// the compiled code of an AOT module
+    pub aot_code: u64,
+}
+
+/// The memory consumed by an instance itself
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstanceMemoryUsage {
+    pub total: u64,
+    pub instance_struct: u64,
+    /// linear memories, including the app heap
+    pub memories: u64,
+    pub app_heap: u64,
+    pub tables: u64,
+    pub functions: u64,
+    pub globals: u64,
+    pub exports: u64,
+}
+
+/// The memory consumed by the execution environment of an instance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecEnvMemoryUsage {
+    pub total: u64,
+    pub exec_env_struct: u64,
+    /// the wasm operand stack
+    pub stack: u64,
+}
+
+/// The memory consumed by an instance, its module and its execution environment
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// the sum of `module`, `instance` and `exec_env`
+    pub total: u64,
+    pub module: ModuleMemoryUsage,
+    pub instance: InstanceMemoryUsage,
+    pub exec_env: ExecEnvMemoryUsage,
+    /// the peak usage of the app heap
+    pub app_heap_used: u64,
+}
+
+const MODULE: &str = "WASM module";
+const INSTANCE: &str = "WASM module inst";
+const EXEC_ENV: &str = "Exec env";
+const SUMMARY: &str = "";
+
+// (section, key) -> value
+type Fields<'a> = BTreeMap<(&'a str, &'a str), u64>;
+
+/// split the output of WAMR memory consumption dumps into fields. like
+///
+/// ```text
+/// WASM module memory consumption, total size: 2048
+///     module struct size: 512
+/// ...
+/// Total app heap used: 0
+/// ```
+fn parse_fields(text: &str) -> Fields<'_> {
+    let mut fields = BTreeMap::new();
+    let mut section = SUMMARY;
+
+    for line in text.lines().map(str::trim) {
+        let (key, value) = match line.rsplit_once(": ") {
+            Some((key, value)) => match value.parse::<u64>() {
+                Ok(value) => (key, value),
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+
+        if let Some((name, _)) = key.split_once(" memory consumption, ") {
+            section = name;
+            fields.insert((section, "total size"), value);
+        } else if key.starts_with("Total ") {
+            fields.insert((SUMMARY, key), value);
+        } else {
+            fields.insert((section, key), value);
+        }
+    }
+    fields
+}
+
+fn module_usage(fields: &Fields<'_>) -> ModuleMemoryUsage {
+    let field = |key| fields.get(&(MODULE, key)).copied().unwrap_or_default();
+    ModuleMemoryUsage {
+        total: field("total size"),
+        module_struct: field("module struct size"),
+        types: field("types size"),
+        imports: field("imports size"),
+        functions: field("funcs size"),
+        tables: field("tables size"),
+        memories: field("memories size"),
+        globals: field("globals size"),
+        exports: field("exports size"),
+        table_segments: field("table segs size"),
+        data_segments: field("data segs size"),
+        const_strings: field("const strings size"),
+        aot_code: field("aot code size"),
+    }
+}
+
+/// parse the output of `wasm_runtime_dump_module_mem_consumption()`
+pub(crate) fn parse_module_usage(text: &str) -> ModuleMemoryUsage {
+    module_usage(&parse_fields(text))
+}
+
+/// parse the output of `wasm_runtime_dump_mem_consumption()`
+pub(crate) fn parse_memory_usage(text: &str) -> MemoryUsage {
+    let fields = parse_fields(text);
+    let field = |section, key| fields.get(&(section, key)).copied().unwrap_or_default();
+
+    MemoryUsage {
+        total: field(
+            SUMMARY,
+            "Total memory consumption of module, module inst and exec env",
+        ),
+        module: module_usage(&fields),
+        instance: InstanceMemoryUsage {
+            total: field(INSTANCE, "total size"),
+            instance_struct: field(INSTANCE, "module inst struct size"),
+            memories: field(INSTANCE, "memories size"),
+            app_heap: field(INSTANCE, "app heap size"),
+            tables: field(INSTANCE, "tables size"),
+            functions: field(INSTANCE, "functions size"),
+            globals: field(INSTANCE, "globals size"),
+            exports: field(INSTANCE, "exports size"),
+        },
+        exec_env: ExecEnvMemoryUsage {
+            total: field(EXEC_ENV, "total size"),
+            exec_env_struct: field(EXEC_ENV, "exec env struct size"),
+            stack: field(EXEC_ENV, "stack size"),
+        },
+        app_heap_used: field(SUMMARY, "Total app heap used"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_usage() {
+        let text = "
+Memory consumption summary (bytes):
+WASM module memory consumption, total size: 2000
+    module struct size: 1000
+    types size: 10
+    imports size: 20
+    funcs size: 30
+    tables size: 0
+    memories size: 40
+    globals size: 50
+    exports size: 60
+    table segs size: 0
+    data segs size: 70
+    const strings size: 80
+    aot code size: 0
+WASM module inst memory consumption, total size: 70000
+    module inst struct size: 300
+    memories size: 65600
+        app heap size: 64
+    tables size: 0
+    functions size: 90
+    globals size: 8
+    exports size: 16
+Exec env memory consumption, total size: 17000
+    exec env struct size: 616
+        block addr cache size: 1024
+    stack size: 16384
+
+Total memory consumption of module, module inst and exec env: 89000
+Total interpreter stack used: 120
+Total auxiliary stack used: 0
+Total app heap used: 32
+";
+
+        let usage = parse_memory_usage(text);
+        assert_eq!(usage.total, 89000);
+        assert_eq!(usage.app_heap_used, 32);
+        assert_eq!(
+            usage.module,
+            ModuleMemoryUsage {
+                total: 2000,
+                module_struct: 1000,
+                types: 10,
+                imports: 20,
+                functions: 30,
+                tables: 0,
+                memories: 40,
+                globals: 50,
+                exports: 60,
+                table_segments: 0,
+                data_segments: 70,
+                const_strings: 80,
+                aot_code: 0,
+            }
+        );
+        assert_eq!(
+            usage.instance,
+            InstanceMemoryUsage {
+                total: 70000,
+                instance_struct: 300,
+                memories: 65600,
+                app_heap: 64,
+                tables: 0,
+                functions: 90,
+                globals: 8,
+                exports: 16,
+            }
+        );
+        assert_eq!(
+            usage.exec_env,
+            ExecEnvMemoryUsage {
+                total: 17000,
+                exec_env_struct: 616,
+                stack: 16384,
+            }
+        );
+
+        let module_text = text.split("WASM module inst").next().unwrap();
+        assert_eq!(parse_module_usage(module_text), usage.module);
+        assert_eq!(parse_memory_usage(""), MemoryUsage::default());
+    }
+}
@@ -6,11 +6,19 @@
 //! .wasm compiled, in-memory representation
 //! get one via `Module::from_file()` or `Module::from_buf()`
 
+#[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+use crate::binary::{export_function_names, function_names, is_wasm_binary};
+#[cfg(feature = "memory-profiling")]
+use crate::memory_usage::{parse_module_usage, ModuleMemoryUsage};
 use crate::{
-    helper::error_buf_to_string, helper::DEFAULT_ERROR_BUF_SIZE, runtime::Runtime,
-    wasi_context::WasiCtx, RuntimeError,
+    helper::error_buf_to_string, helper::DEFAULT_ERROR_BUF_SIZE, policy::ModulePolicy,
+    runtime::Runtime, types::ImportType, wasi_context::WasiCtx, RuntimeError,
 };
 use core::marker::PhantomData;
+#[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+use std::collections::BTreeMap;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
 use std::{
     ffi::{c_char, CString},
     fs::File,
@@ -20,10 +28,12 @@ use std::{
     string::String,
     vec::Vec,
 };
+#[cfg(feature = "memory-profiling")]
+use wamr_sys::wasm_runtime_dump_module_mem_consumption;
 use wamr_sys::{
-    wasm_module_t, wasm_runtime_load, wasm_runtime_set_module_name,
-    wasm_runtime_set_wasi_addr_pool, wasm_runtime_set_wasi_args,
-    wasm_runtime_set_wasi_ns_lookup_pool, wasm_runtime_unload,
+    wasm_import_t, wasm_module_t, wasm_runtime_get_import_count, wasm_runtime_get_import_type,
+    wasm_runtime_load, wasm_runtime_set_module_name, wasm_runtime_set_wasi_addr_pool,
+    wasm_runtime_set_wasi_args, wasm_runtime_set_wasi_ns_lookup_pool, wasm_runtime_unload,
 };
 
 #[allow(dead_code)]
@@ -33,6 +43,9 @@ pub struct Module<'runtime> {
     module: wasm_module_t,
     // to keep the module content in memory
     content: Vec<u8>,
+    // by function index. parsed before WAMR rewrites names in `content`
+    #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+    function_names: BTreeMap<u32, String>,
     wasi_ctx: WasiCtx,
     _phantom: PhantomData<&'runtime Runtime>,
 }
@@ -45,13 +58,34 @@ impl<'runtime> Module<'runtime> {
     /// If the file does not exist or the file cannot be read, an `RuntimeError::WasmFileFSError` will be returned.
     /// If the wasm file is not a valid wasm file, an `RuntimeError::CompilationError` will be returned.
     pub fn from_file(runtime: &'runtime Runtime, wasm_file: &Path) -> Result<Self, RuntimeError> {
+        let (binary, name) = Self::read_file(wasm_file)?;
+        Self::from_vec(runtime, binary, &name)
+    }
+
+    /// compile a module with the given wasm file path and check it against the policy
+    ///
+    /// # Error
+    ///
+    /// Besides errors of `Module::from_file()`, if the module breaks the policy,
+    /// an `RuntimeError::PolicyViolation` will be returned.
+    pub fn from_file_with_policy(
+        runtime: &'runtime Runtime,
+        wasm_file: &Path,
+        policy: &ModulePolicy,
+    ) -> Result<Self, RuntimeError> {
+        let (binary, name) = Self::read_file(wasm_file)?;
+        Self::from_vec_with_policy(runtime, binary, &name, policy)
+    }
+
+    fn read_file(wasm_file: &Path) -> Result<(Vec<u8>, String), RuntimeError> {
         let name = wasm_file.file_name().unwrap().to_str().unwrap();
+        let name = String::from(name);
         let mut wasm_file = File::open(wasm_file)?;
 
         let mut binary: Vec<u8> = Vec::new();
         wasm_file.read_to_end(&mut binary)?;
 
-        Self::from_vec(runtime, binary, name)
+        Ok((binary, name))
     }
 
     /// compile a module int the given buffer,
@@ -61,10 +95,67 @@ impl<'runtime> Module<'runtime> {
     /// If the file does not exist or the file cannot be read, an `RuntimeError::WasmFileFSError` will be returned.
     /// If the wasm file is not a valid wasm file, an `RuntimeError::CompilationError` will be returned.
     pub fn from_vec(
-        _runtime: &'runtime Runtime,
+        runtime: &'runtime Runtime,
+        content: Vec<u8>,
+        name: &str,
+    ) -> Result<Self, RuntimeError> {
+        Self::load(runtime, content, name, None)
+    }
+
+    /// compile a module in the given buffer and check it against the policy
+    /// before any of its imports is resolved
+    ///
+    /// # Error
+    ///
+    /// Besides errors of `Module::from_vec()`, if the module breaks the policy,
+    /// an `RuntimeError::PolicyViolation` will be returned.
+    pub fn from_vec_with_policy(
+        runtime: &'runtime Runtime,
+        content: Vec<u8>,
+        name: &str,
+        policy: &ModulePolicy,
+    ) -> Result<Self, RuntimeError> {
+        Self::load(runtime, content, name, Some(policy))
+    }
+
+    fn load(
+        runtime: &'runtime Runtime,
+        content: Vec<u8>,
+        name: &str,
+        policy: Option<&ModulePolicy>,
+    ) -> Result<Self, RuntimeError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "wamr.load",
+            module = name,
+            size = content.len(),
+            duration_us = tracing::field::Empty,
+            error = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        let start = Instant::now();
+
+        let result = Self::load_inner(runtime, content, name, policy);
+
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&span, start, &result);
+        result
+    }
+
+    fn load_inner(
+        runtime: &'runtime Runtime,
         mut content: Vec<u8>,
         name: &str,
+        policy: Option<&ModulePolicy>,
     ) -> Result<Self, RuntimeError> {
+        // WAMR rewrites names in `content` in place while loading
+        if let Some(policy) = policy {
+            policy.check_limits(&content)?;
+        }
+        #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+        let function_names = Self::parse_function_names(&content);
+
         let mut error_buf: [c_char; DEFAULT_ERROR_BUF_SIZE] = [0; DEFAULT_ERROR_BUF_SIZE];
         let module = unsafe {
             wasm_runtime_load(
@@ -90,6 +181,18 @@ impl<'runtime> Module<'runtime> {
             }
         }
 
+        if let Some(policy) = policy {
+            if let Err(e) = policy.check_imports(&Self::get_raw_module_imports(module)) {
+                unsafe { wasm_runtime_unload(module) };
+                return Err(e);
+            }
+        }
+
+        if let Err(e) = runtime.resolve_imports(module) {
+            unsafe { wasm_runtime_unload(module) };
+            return Err(e);
+        }
+
         unsafe {
             let name_c = CString::new(name.as_bytes()).unwrap();
             if !wasm_runtime_set_module_name(
@@ -108,6 +211,8 @@ impl<'runtime> Module<'runtime> {
             name: String::from(name),
             module,
             content,
+            #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+            function_names,
             wasi_ctx: WasiCtx::default(),
             _phantom: PhantomData,
         })
@@ -181,6 +286,31 @@ impl<'runtime> Module<'runtime> {
         }
     }
 
+    /// list all imports of the module, in the order of the import section
+    pub fn get_imports(&self) -> Vec<ImportType> {
+        Self::get_raw_module_imports(self.module)
+    }
+
+    /// the memory consumed by the module after loading
+    #[cfg(feature = "memory-profiling")]
+    pub fn memory_usage(&self) -> ModuleMemoryUsage {
+        let dump = crate::log_hook::capture(|| unsafe {
+            wasm_runtime_dump_module_mem_consumption(self.module)
+        });
+        parse_module_usage(&dump)
+    }
+
+    pub(crate) fn get_raw_module_imports(module: wasm_module_t) -> Vec<ImportType> {
+        let import_count = unsafe { wasm_runtime_get_import_count(module) };
+        (0..import_count)
+            .map(|i| unsafe {
+                let mut import = wasm_import_t::default();
+                wasm_runtime_get_import_type(module, i, &mut import);
+                ImportType::from_raw(&import)
+            })
+            .collect()
+    }
+
     pub fn get_inner_module(&self) -> wasm_module_t {
         self.module
     }
@@ -188,6 +318,28 @@ impl<'runtime> Module<'runtime> {
     pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    pub(crate) fn get_content(&self) -> &[u8] {
+        &self.content
+    }
+
+    /// function names by index, for call stacks and profiles
+    #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+    pub(crate) fn get_function_names(&self) -> &BTreeMap<u32, String> {
+        &self.function_names
+    }
+
+    // like WAMR, prefer names in the name section to export names.
+    // both are optional, it is fine without names
+    #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+    fn parse_function_names(content: &[u8]) -> BTreeMap<u32, String> {
+        let mut names = BTreeMap::new();
+        if is_wasm_binary(content) {
+            names.extend(export_function_names(content).unwrap_or_default());
+            names.extend(function_names(content).unwrap_or_default());
+        }
+        names
+    }
 }
 
 impl Drop for Module<'_> {
@@ -201,7 +353,13 @@ impl Drop for Module<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{helper::cstr_to_string, runtime::Runtime, wasi_context::WasiCtxBuilder};
+    use crate::{
+        helper::cstr_to_string,
+        runtime::Runtime,
+        types::{ExternType, FuncType},
+        value::WasmValType,
+        wasi_context::WasiCtxBuilder,
+    };
     use std::path::PathBuf;
     use wamr_sys::wasm_runtime_get_module_name;
 
@@ -309,4 +467,61 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_module_imports() -> Result<(), RuntimeError> {
+        let runtime = Runtime::new()?;
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("add_extra_wasm32_wasi.wasm");
+        let module = Module::from_file(&runtime, d.as_path())?;
+
+        let imports = module.get_imports();
+        let extra = imports
+            .iter()
+            .find(|import| import.module_name == "host" && import.name == "extra");
+        assert!(extra.is_some());
+
+        let extra = extra.unwrap();
+        assert!(!extra.linked);
+        assert_eq!(
+            extra.ty,
+            ExternType::Func(FuncType {
+                params: vec![],
+                results: vec![WasmValType::I32],
+            })
+        );
+
+        assert!(imports
+            .iter()
+            .filter(|import| import.module_name == "wasi_snapshot_preview1")
+            .all(|import| import.linked));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(feature = "dump-call-stack", feature = "perf-profiling"))]
+    fn test_module_function_names() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (func $helper (result i32) (i32.const 42))
+        //   (func (export "run") (result i32) (i32.const 42))
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x00, 0x01,
+            0x7f, 0x03, 0x03, 0x02, 0x00, 0x00, 0x07, 0x07, 0x01, 0x03, 0x72, 0x75, 0x6e, 0x00,
+            0x01, 0x0a, 0x0b, 0x02, 0x04, 0x00, 0x41, 0x2a, 0x0b, 0x04, 0x00, 0x41, 0x2a, 0x0b,
+            0x00, 0x10, 0x04, 0x6e, 0x61, 0x6d, 0x65, 0x01, 0x09, 0x01, 0x00, 0x06, 0x68, 0x65,
+            0x6c, 0x70, 0x65, 0x72,
+        ];
+        let module = Module::from_vec(&runtime, binary, "names").unwrap();
+
+        // WAMR has rewritten the names in the content by now
+        let names = module.get_function_names();
+        assert_eq!(names.get(&0).map(String::as_str), Some("helper"));
+        assert_eq!(names.get(&1).map(String::as_str), Some("run"));
+    }
 }
@@ -0,0 +1,334 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! rules a module has to obey to be loaded.
+//! use with `Module::from_vec_with_policy()` or `Module::from_file_with_policy()`
+
+use crate::{
+    binary::{declared_limits, is_wasm_binary, Limits},
+    types::ImportType,
+    RuntimeError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ImportRule {
+    /// every import from the module name
+    Namespace(String),
+    /// an import with the module name and the name
+    Import(String, String),
+}
+
+impl ImportRule {
+    fn matches(&self, import: &ImportType) -> bool {
+        match self {
+            ImportRule::Namespace(module_name) => import.module_name == *module_name,
+            ImportRule::Import(module_name, name) => {
+                import.module_name == *module_name && import.name == *name
+            }
+        }
+    }
+}
+
+/// The policy of imports and sizes of a module.
+///
+/// - an import matches any deny rule is rejected.
+/// - if there is any allow rule, an import has to match one of them.
+/// - every memory and table has to declare sizes within the limits. The initial size
+///   is always checked, the maximum only if declared. Most toolchains declare none,
+///   so cap the growth of memories with `InstanceBuilder::max_memory_pages()`.
+/// - an .aot can't be checked for limits. With any limit set, it is rejected.
+#[derive(Debug, Default, Clone)]
+pub struct ModulePolicy {
+    allowed_imports: Vec<ImportRule>,
+    denied_imports: Vec<ImportRule>,
+    max_memory_pages: Option<u64>,
+    max_table_size: Option<u64>,
+}
+
+impl ModulePolicy {
+    pub fn new() -> ModulePolicy {
+        ModulePolicy::default()
+    }
+
+    /// allow the import `module_name`.`name`
+    pub fn allow_import(mut self, module_name: &str, name: &str) -> ModulePolicy {
+        self.allowed_imports.push(ImportRule::Import(
+            String::from(module_name),
+            String::from(name),
+        ));
+        self
+    }
+
+    /// allow all imports from `module_name`. like *wasi_snapshot_preview1*
+    pub fn allow_namespace(mut self, module_name: &str) -> ModulePolicy {
+        self.allowed_imports
+            .push(ImportRule::Namespace(String::from(module_name)));
+        self
+    }
+
+    /// deny the import `module_name`.`name`. like *wasi_snapshot_preview1*.*sock_open*
+    pub fn deny_import(mut self, module_name: &str, name: &str) -> ModulePolicy {
+        self.denied_imports.push(ImportRule::Import(
+            String::from(module_name),
+            String::from(name),
+        ));
+        self
+    }
+
+    /// deny all imports from `module_name`
+    pub fn deny_namespace(mut self, module_name: &str) -> ModulePolicy {
+        self.denied_imports
+            .push(ImportRule::Namespace(String::from(module_name)));
+        self
+    }
+
+    /// limit the declared size of memories, in 64KiB pages
+    pub fn max_memory_pages(mut self, pages: u64) -> ModulePolicy {
+        self.max_memory_pages = Some(pages);
+        self
+    }
+
+    /// limit the declared size of tables, in elements
+    pub fn max_table_size(mut self, size: u64) -> ModulePolicy {
+        self.max_table_size = Some(size);
+        self
+    }
+
+    /// check imports of a module
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::PolicyViolation` with the first import breaking the rules.
+    pub fn check_imports(&self, imports: &[ImportType]) -> Result<(), RuntimeError> {
+        for import in imports {
+            if self.denied_imports.iter().any(|rule| rule.matches(import)) {
+                return Err(RuntimeError::PolicyViolation(format!(
+                    "import {}.{} is denied",
+                    import.module_name, import.name
+                )));
+            }
+
+            if !self.allowed_imports.is_empty()
+                && !self.allowed_imports.iter().any(|rule| rule.matches(import))
+            {
+                return Err(RuntimeError::PolicyViolation(format!(
+                    "import {}.{} is not allowed",
+                    import.module_name, import.name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// check sizes of memories and tables declared in a module binary
+    ///
+    /// # Error
+    ///
+    /// Return `RuntimeError::PolicyViolation` with the first memory or table exceeding limits,
+    /// or if `content` is an .aot and any limit is set.
+    /// It has to run before `wasm_runtime_load()`, which rewrites names in `content`.
+    pub fn check_limits(&self, content: &[u8]) -> Result<(), RuntimeError> {
+        if self.max_memory_pages.is_none() && self.max_table_size.is_none() {
+            return Ok(());
+        }
+        if !is_wasm_binary(content) {
+            return Err(RuntimeError::PolicyViolation(String::from(
+                "memory and table limits can't be checked for an AOT module",
+            )));
+        }
+
+        let limits = declared_limits(content).map_err(RuntimeError::CompilationError)?;
+
+        if let Some(limit) = self.max_memory_pages {
+            for (index, memory) in limits.memories.iter().enumerate() {
+                check_limit("memory", index, memory, limit, "pages")?;
+            }
+        }
+
+        if let Some(limit) = self.max_table_size {
+            for (index, table) in limits.tables.iter().enumerate() {
+                check_limit("table", index, table, limit, "elements")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// the maximum if declared, the initial size otherwise. growth beyond is left to the runtime
+fn check_limit(
+    kind: &str,
+    index: usize,
+    limits: &Limits,
+    limit: u64,
+    unit: &str,
+) -> Result<(), RuntimeError> {
+    let declared = limits.max.unwrap_or(limits.min);
+    if declared <= limit {
+        return Ok(());
+    }
+
+    Err(RuntimeError::PolicyViolation(format!(
+        "{} {} declares {} {}, exceeds the limit {}",
+        kind, index, declared, unit, limit
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{module::Module, runtime::Runtime, types::ExternType, types::FuncType};
+    use std::path::PathBuf;
+
+    fn func_import(module_name: &str, name: &str) -> ImportType {
+        ImportType {
+            module_name: String::from(module_name),
+            name: String::from(name),
+            ty: ExternType::Func(FuncType {
+                params: vec![],
+                results: vec![],
+            }),
+            linked: true,
+        }
+    }
+
+    #[test]
+    fn test_policy_imports() {
+        let imports = vec![
+            func_import("wasi_snapshot_preview1", "fd_write"),
+            func_import("wasi_snapshot_preview1", "sock_open"),
+            func_import("env", "log"),
+        ];
+
+        assert!(ModulePolicy::new().check_imports(&imports).is_ok());
+
+        let policy = ModulePolicy::new().deny_import("wasi_snapshot_preview1", "sock_open");
+        assert!(policy.check_imports(&imports).is_err());
+        assert!(policy.check_imports(&imports[0..1]).is_ok());
+
+        let policy = ModulePolicy::new().deny_namespace("env");
+        assert!(policy.check_imports(&imports).is_err());
+        assert!(policy.check_imports(&imports[0..2]).is_ok());
+
+        let policy = ModulePolicy::new().allow_namespace("wasi_snapshot_preview1");
+        assert!(policy.check_imports(&imports).is_err());
+        assert!(policy.check_imports(&imports[0..2]).is_ok());
+
+        let policy = ModulePolicy::new()
+            .allow_namespace("wasi_snapshot_preview1")
+            .allow_import("env", "log")
+            .deny_import("wasi_snapshot_preview1", "sock_open");
+        assert!(policy.check_imports(&imports).is_err());
+        assert!(policy
+            .check_imports(&[imports[0].clone(), imports[2].clone()])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_policy_limits() {
+        // (module
+        //   (table 3 3 funcref)
+        //   (memory 4 8)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x04, 0x05, 0x01, 0x70, 0x01, 0x03,
+            0x03, 0x05, 0x04, 0x01, 0x01, 0x04, 0x08,
+        ];
+
+        assert!(ModulePolicy::new().check_limits(&binary).is_ok());
+        assert!(ModulePolicy::new()
+            .max_memory_pages(8)
+            .max_table_size(3)
+            .check_limits(&binary)
+            .is_ok());
+        assert!(ModulePolicy::new()
+            .max_memory_pages(4)
+            .check_limits(&binary)
+            .is_err());
+        assert!(ModulePolicy::new()
+            .max_table_size(2)
+            .check_limits(&binary)
+            .is_err());
+
+        // no maximum, as rustc and clang output
+        // (module
+        //   (memory 17)
+        // )
+        let unbounded = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x05, 0x03, 0x01, 0x00, 0x11,
+        ];
+        assert!(ModulePolicy::new().check_limits(&unbounded).is_ok());
+        assert!(ModulePolicy::new()
+            .max_memory_pages(17)
+            .check_limits(&unbounded)
+            .is_ok());
+        assert!(matches!(
+            ModulePolicy::new()
+                .max_memory_pages(16)
+                .check_limits(&unbounded),
+            Err(RuntimeError::PolicyViolation(_))
+        ));
+
+        let aot = b"\0aot\x03\x00\x00\x00";
+        assert!(ModulePolicy::new().check_limits(aot).is_ok());
+        assert!(matches!(
+            ModulePolicy::new().max_table_size(10).check_limits(aot),
+            Err(RuntimeError::PolicyViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_module_from_vec_with_policy_limits() {
+        let runtime = Runtime::new().unwrap();
+
+        // (module
+        //   (import "env" "log" (func (param i32)))
+        //   (table 1 1 funcref)
+        //   (memory 1 2)
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x01, 0x7f,
+            0x00, 0x02, 0x0b, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x03, 0x6c, 0x6f, 0x67, 0x00, 0x00,
+            0x04, 0x05, 0x01, 0x70, 0x01, 0x01, 0x01, 0x05, 0x04, 0x01, 0x01, 0x01, 0x02,
+        ];
+
+        let policy = ModulePolicy::new()
+            .allow_namespace("env")
+            .max_memory_pages(2)
+            .max_table_size(1);
+        let module = Module::from_vec_with_policy(&runtime, binary.clone(), "limits", &policy);
+        assert!(module.is_ok());
+
+        let policy = ModulePolicy::new()
+            .allow_namespace("env")
+            .max_memory_pages(1);
+        let module = Module::from_vec_with_policy(&runtime, binary.clone(), "limits", &policy);
+        assert!(matches!(module, Err(RuntimeError::PolicyViolation(_))));
+
+        let policy = ModulePolicy::new()
+            .deny_import("env", "log")
+            .max_memory_pages(2);
+        let module = Module::from_vec_with_policy(&runtime, binary, "limits", &policy);
+        assert!(matches!(module, Err(RuntimeError::PolicyViolation(_))));
+    }
+
+    #[test]
+    fn test_module_from_file_with_policy() {
+        let runtime = Runtime::new().unwrap();
+
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("resources/test");
+        d.push("gcd_wasm32_wasi.wasm");
+
+        let policy = ModulePolicy::new().allow_namespace("wasi_snapshot_preview1");
+        let module = Module::from_file_with_policy(&runtime, d.as_path(), &policy);
+        assert!(module.is_ok());
+
+        let policy = ModulePolicy::new().deny_namespace("wasi_snapshot_preview1");
+        let module = Module::from_file_with_policy(&runtime, d.as_path(), &policy);
+        assert!(matches!(module, Err(RuntimeError::PolicyViolation(_))));
+    }
+}
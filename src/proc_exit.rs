@@ -0,0 +1,69 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! the WASI `proc_exit` of the SDK, which tells an exit from a return.
+//!
+//! WAMR clears the exception raised by its own `proc_exit` and reports a success, so
+//! the exit code is the only trace left, and it doesn't change when a program exits
+//! twice with the same code. The SDK registers a `proc_exit` which records the exit of
+//! the instance before raising the same exception. Natives registered later are
+//! looked up first, so it hides the one of WAMR.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, c_void},
+    sync::{Mutex, MutexGuard},
+};
+
+use wamr_sys::{
+    wasm_exec_env_t, wasm_module_inst_t, wasm_runtime_get_module_inst,
+    wasm_runtime_register_natives, wasm_runtime_set_exception,
+};
+
+use crate::host_function::HostFunctionList;
+
+// the exit code of instances which call `proc_exit`, by the address of the instance
+static EXITS: Mutex<BTreeMap<usize, u32>> = Mutex::new(BTreeMap::new());
+
+// a panic while holding the lock can't leave the map half updated
+fn exits() -> MutexGuard<'static, BTreeMap<usize, u32>> {
+    EXITS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+extern "C" fn proc_exit(exec_env: wasm_exec_env_t, exit_code: u32) {
+    unsafe {
+        let instance = wasm_runtime_get_module_inst(exec_env);
+        exits().insert(instance as usize, exit_code);
+        // the exception WAMR expects to unwind and clear
+        wasm_runtime_set_exception(instance, b"wasi proc exit\0".as_ptr() as *const c_char);
+    }
+}
+
+/// the native symbols to keep until the runtime is destroyed
+pub(crate) fn host_functions() -> HostFunctionList {
+    let mut host_functions = HostFunctionList::new("wasi_snapshot_preview1");
+    host_functions.register_host_function("proc_exit", proc_exit as *mut c_void);
+    host_functions
+}
+
+/// register `host_functions()` after the runtime is initialized
+pub(crate) fn register(host_functions: &mut HostFunctionList) -> bool {
+    unsafe {
+        let module_name = host_functions.get_module_name().as_ptr();
+        let native_symbols = host_functions.get_native_symbols();
+        wasm_runtime_register_natives(
+            module_name,
+            native_symbols.as_mut_ptr(),
+            native_symbols.len() as u32,
+        )
+    }
+}
+
+/// the exit code if the instance called `proc_exit` since the last time
+pub(crate) fn take(instance: wasm_module_inst_t) -> Option<u32> {
+    exits().remove(&(instance as usize))
+}
@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! per-function execution time of an instance, with the `perf-profiling` feature.
+//! get one via `Instance::profile()`
+
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::backtrace::func_index_of;
+
+/// The execution profile of an instance since it is instantiated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// the time spent in all wasm functions
+    pub total_time: Duration,
+    /// functions called at least once, the one with the most self time first
+    pub functions: Vec<FunctionProfile>,
+}
+
+/// The execution profile of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// the function index, including imported functions
+    pub func_index: Option<u32>,
+    /// the name from the name section or the export
+    pub func_name: Option<String>,
+    pub call_count: u32,
+    /// the time spent in the function and the functions it calls
+    pub total_time: Duration,
+    /// the time spent in the function itself
+    pub self_time: Duration,
+}
+
+impl Profile {
+    /// the profile of the function with the name
+    pub fn get_function(&self, name: &str) -> Option<&FunctionProfile> {
+        self.functions
+            .iter()
+            .find(|function| function.func_name.as_deref() == Some(name))
+    }
+}
+
+/// WAMR reports execution time in milliseconds
+pub(crate) fn millis(ms: f64) -> Duration {
+    Duration::from_micros((ms.max(0.0) * 1000.0).round() as u64)
+}
+
+/// parse the output of `wasm_runtime_dump_perf_profiling()`. like
+///
+/// ```text
+/// Performance profiler data:
+///   func fib, execution time: 12.500 ms, execution count: 177 times, children execution time: 10.250 ms
+///   func 3, execution time: 0.010 ms, execution count: 1 times, children execution time: 0.000 ms
+/// ```
+///
+/// `names` are function names by index, usually from the name section
+pub(crate) fn parse_perf_profiling(
+    text: &str,
+    names: &BTreeMap<u32, String>,
+) -> Vec<FunctionProfile> {
+    let mut functions: Vec<FunctionProfile> = text
+        .lines()
+        .filter_map(|line| parse_function(line.trim(), names))
+        .filter(|function| function.call_count > 0)
+        .collect();
+    functions.sort_by(|a, b| b.self_time.cmp(&a.self_time));
+    functions
+}
+
+fn parse_function(line: &str, names: &BTreeMap<u32, String>) -> Option<FunctionProfile> {
+    let line = line.strip_prefix("func ")?;
+    // a name may have commas, like a C++ one
+    let (func, rest) = line.split_at(line.rfind(", execution time: ")?);
+
+    let mut total_ms = None;
+    let mut call_count = None;
+    let mut children_ms = None;
+    for field in rest.split(", ").filter(|field| !field.is_empty()) {
+        let (key, value) = field.split_once(": ")?;
+        match key {
+            "execution time" => total_ms = value.strip_suffix(" ms")?.parse::<f64>().ok(),
+            "execution count" => call_count = value.strip_suffix(" times")?.parse::<u32>().ok(),
+            "children execution time" => {
+                children_ms = value.strip_suffix(" ms")?.parse::<f64>().ok()
+            }
+            _ => {}
+        }
+    }
+
+    let total_time = millis(total_ms?);
+    let (func_index, func_name) = match func.parse::<u32>() {
+        Ok(index) => (Some(index), names.get(&index).cloned()),
+        Err(_) => (func_index_of(names, func), Some(String::from(func))),
+    };
+
+    Some(FunctionProfile {
+        func_index,
+        func_name,
+        call_count: call_count?,
+        total_time,
+        // older WAMR doesn't report children
+        self_time: total_time.saturating_sub(children_ms.map(millis).unwrap_or_default()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_perf_profiling() {
+        let text = "Performance profiler data:\n  \
+            func fib, execution time: 12.500 ms, execution count: 177 times, children execution time: 10.250 ms\n  \
+            func 3, execution time: 20.000 ms, execution count: 1 times, children execution time: 12.500 ms\n  \
+            func 4, execution time: 0.000 ms, execution count: 0 times, children execution time: 0.000 ms\n";
+        let names = BTreeMap::from([(2, String::from("fib")), (3, String::from("main"))]);
+
+        let functions = parse_perf_profiling(text, &names);
+        assert_eq!(
+            functions,
+            vec![
+                FunctionProfile {
+                    func_index: Some(3),
+                    func_name: Some(String::from("main")),
+                    call_count: 1,
+                    total_time: Duration::from_millis(20),
+                    self_time: Duration::from_micros(7500),
+                },
+                FunctionProfile {
+                    func_index: Some(2),
+                    func_name: Some(String::from("fib")),
+                    call_count: 177,
+                    total_time: Duration::from_micros(12500),
+                    self_time: Duration::from_micros(2250),
+                },
+            ]
+        );
+
+        let profile = Profile {
+            total_time: millis(20.0),
+            functions,
+        };
+        assert_eq!(profile.get_function("fib").unwrap().call_count, 177);
+        assert!(profile.get_function("$f4").is_none());
+    }
+
+    #[test]
+    fn test_parse_perf_profiling_invalid() {
+        let names = BTreeMap::new();
+        assert!(parse_perf_profiling("", &names).is_empty());
+        assert!(parse_perf_profiling("func fib, execution time: ms", &names).is_empty());
+    }
+}
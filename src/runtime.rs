@@ -4,23 +4,306 @@
  */
 
 //! This is the main entry point for executing WebAssembly modules.
-//! Every process should have only one instance of this runtime by call
-//! `Runtime::new()` or `Runtime::builder().build()` once.
+//! WAMR has one runtime per process. `Runtime::new()` and `Runtime::builder().build()`
+//! return reference-counted handles of it. It is destroyed with the last handle.
 
-use std::ffi::c_void;
+#[cfg(feature = "debug-interp")]
+use std::ffi::c_char;
+use std::{
+    alloc::GlobalAlloc,
+    collections::BTreeMap,
+    ffi::c_void,
+    ops::BitOr,
+    sync::{Arc, Mutex},
+};
 
 use wamr_sys::{
-    mem_alloc_type_t_Alloc_With_Pool, mem_alloc_type_t_Alloc_With_System_Allocator,
-    wasm_runtime_destroy, wasm_runtime_full_init, wasm_runtime_init, NativeSymbol,
-    RunningMode_Mode_Interp, RunningMode_Mode_LLVM_JIT, RuntimeInitArgs,
+    log_level_t, log_level_t_WASM_LOG_LEVEL_DEBUG, log_level_t_WASM_LOG_LEVEL_ERROR,
+    log_level_t_WASM_LOG_LEVEL_FATAL, log_level_t_WASM_LOG_LEVEL_VERBOSE,
+    log_level_t_WASM_LOG_LEVEL_WARNING, mem_alloc_info_t, mem_alloc_type_t_Alloc_With_Allocator,
+    mem_alloc_type_t_Alloc_With_Pool, mem_alloc_type_t_Alloc_With_System_Allocator, wasm_module_t,
+    wasm_runtime_destroy, wasm_runtime_full_init, wasm_runtime_get_mem_alloc_info,
+    wasm_runtime_init, wasm_runtime_is_running_mode_supported, wasm_runtime_register_natives_raw,
+    wasm_runtime_resolve_symbols, wasm_runtime_set_log_level, NativeSymbol,
+    RunningMode_Mode_Default, RunningMode_Mode_Fast_JIT, RunningMode_Mode_Interp,
+    RunningMode_Mode_LLVM_JIT, RunningMode_Mode_Multi_Tier_JIT, RuntimeInitArgs,
+    WAMR_BUILD_DEBUG_INTERP, WAMR_BUILD_FAST_JIT, WAMR_BUILD_GC, WAMR_BUILD_JIT,
+    WAMR_BUILD_LIB_WASI_THREADS, WAMR_BUILD_MEMORY64, WAMR_BUILD_MEMORY_PROFILING,
+    WAMR_BUILD_PERF_PROFILING, WAMR_BUILD_SHARED_MEMORY, WAMR_BUILD_SIMD,
+};
+
+use crate::{
+    allocator,
+    host_function::{HostFunc, HostFunctionList, ImportResolver, RawHostFunctionList},
+    module::Module,
+    proc_exit,
+    types::{ExternType, FuncType},
+    RuntimeError,
 };
 
-use crate::{host_function::HostFunctionList, RuntimeError};
+/// How much WAMR logs. Each level includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Fatal,
+    Error,
+    Warning,
+    Debug,
+    Verbose,
+}
+
+impl From<LogLevel> for log_level_t {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Fatal => log_level_t_WASM_LOG_LEVEL_FATAL,
+            LogLevel::Error => log_level_t_WASM_LOG_LEVEL_ERROR,
+            LogLevel::Warning => log_level_t_WASM_LOG_LEVEL_WARNING,
+            LogLevel::Debug => log_level_t_WASM_LOG_LEVEL_DEBUG,
+            LogLevel::Verbose => log_level_t_WASM_LOG_LEVEL_VERBOSE,
+        }
+    }
+}
+
+/// The usage of the memory pool, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAllocInfo {
+    pub total_size: u32,
+    pub total_free_size: u32,
+    /// the peak usage
+    pub highmark_size: u32,
+}
+
+/// How wasm functions are executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunningMode {
+    Interp,
+    FastJit,
+    LlvmJit,
+    /// start with Fast JIT, and recompile hot functions with LLVM JIT
+    MultiTierJit,
+}
+
+impl RunningMode {
+    /// the cargo feature to compile the mode in
+    pub(crate) fn feature(self) -> &'static str {
+        match self {
+            RunningMode::Interp => "interp",
+            RunningMode::FastJit => "fast-jit",
+            RunningMode::LlvmJit => "llvmjit",
+            RunningMode::MultiTierJit => "multi-tier-jit",
+        }
+    }
+
+    #[allow(non_upper_case_globals)]
+    pub(crate) fn from_raw(mode: wamr_sys::RunningMode) -> Option<RunningMode> {
+        match mode {
+            RunningMode_Mode_Interp => Some(RunningMode::Interp),
+            RunningMode_Mode_Fast_JIT => Some(RunningMode::FastJit),
+            RunningMode_Mode_LLVM_JIT => Some(RunningMode::LlvmJit),
+            RunningMode_Mode_Multi_Tier_JIT => Some(RunningMode::MultiTierJit),
+            _ => None,
+        }
+    }
+
+    /// the interpreter is always compiled in
+    pub(crate) fn is_compiled_in(self) -> bool {
+        match self {
+            RunningMode::Interp => true,
+            RunningMode::FastJit => WAMR_BUILD_FAST_JIT,
+            RunningMode::LlvmJit => WAMR_BUILD_JIT,
+            // WAMR builds both JITs as multi-tier JIT
+            RunningMode::MultiTierJit => WAMR_BUILD_FAST_JIT && WAMR_BUILD_JIT,
+        }
+    }
+}
+
+impl From<RunningMode> for wamr_sys::RunningMode {
+    fn from(mode: RunningMode) -> Self {
+        match mode {
+            RunningMode::Interp => RunningMode_Mode_Interp,
+            RunningMode::FastJit => RunningMode_Mode_Fast_JIT,
+            RunningMode::LlvmJit => RunningMode_Mode_LLVM_JIT,
+            RunningMode::MultiTierJit => RunningMode_Mode_Multi_Tier_JIT,
+        }
+    }
+}
+
+/// The WAMR build features compiled in, see `Runtime::capabilities()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub interp: bool,
+    pub fast_jit: bool,
+    pub llvm_jit: bool,
+    pub multi_tier_jit: bool,
+    /// what instances run in unless `Instance::set_running_mode()`
+    pub default_running_mode: RunningMode,
+    /// v128 in AOT modules and LLVM JIT
+    pub simd: bool,
+    /// shared memories and atomics
+    pub threads: bool,
+    pub gc: bool,
+    pub memory64: bool,
+    /// the debug server of `Instance::start_debug_server()`
+    pub debug: bool,
+    /// `Instance::profile()`
+    pub perf_profiling: bool,
+    /// `Instance::memory_usage()`
+    pub memory_profiling: bool,
+}
+
+/// Which memory accesses LLVM JIT addresses by the GS segment register, instead
+/// of adding the linear memory base. x86-64 Linux only. Combine flags with `|`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegueFlags(u32);
+
+impl SegueFlags {
+    pub const NONE: SegueFlags = SegueFlags(0);
+    pub const I32_LOAD: SegueFlags = SegueFlags(1 << 0);
+    pub const I64_LOAD: SegueFlags = SegueFlags(1 << 1);
+    pub const F32_LOAD: SegueFlags = SegueFlags(1 << 2);
+    pub const F64_LOAD: SegueFlags = SegueFlags(1 << 3);
+    pub const V128_LOAD: SegueFlags = SegueFlags(1 << 4);
+    pub const I32_STORE: SegueFlags = SegueFlags(1 << 8);
+    pub const I64_STORE: SegueFlags = SegueFlags(1 << 9);
+    pub const F32_STORE: SegueFlags = SegueFlags(1 << 10);
+    pub const F64_STORE: SegueFlags = SegueFlags(1 << 11);
+    pub const V128_STORE: SegueFlags = SegueFlags(1 << 12);
+    pub const ALL: SegueFlags = SegueFlags(0x1f1f);
+
+    /// the bits passed to WAMR
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for SegueFlags {
+    type Output = SegueFlags;
+
+    fn bitor(self, rhs: SegueFlags) -> SegueFlags {
+        SegueFlags(self.0 | rhs.0)
+    }
+}
+
+/// the highest `llvm_jit_opt_level` and `llvm_jit_size_level`, as `wamrc`
+const MAX_LLVM_JIT_LEVEL: u32 = 3;
+
+fn check_llvm_jit_level(name: &str, level: u32) -> Result<u32, RuntimeError> {
+    if !cfg!(feature = "llvmjit") {
+        return Err(RuntimeError::FeatureNotEnabled("llvmjit"));
+    }
+    if level > MAX_LLVM_JIT_LEVEL {
+        return Err(RuntimeError::InvalidConfiguration(format!(
+            "llvm jit {} {} is out of 0..={}",
+            name, level, MAX_LLVM_JIT_LEVEL
+        )));
+    }
+    Ok(level)
+}
+
+/// what tells two runtime configurations apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RuntimeConfig {
+    mem_alloc_type: u32,
+    pool_size: u32,
+    // the address of the allocator
+    allocator: usize,
+    running_mode: wamr_sys::RunningMode,
+    llvm_jit_opt_level: u32,
+    llvm_jit_size_level: u32,
+    segue_flags: u32,
+    max_thread_num: u32,
+    fast_jit_code_cache_size: u32,
+    gc_heap_size: u32,
+    // the module name and (name, function pointer) of host functions
+    host_module_name: String,
+    host_functions: Vec<(String, usize)>,
+    import_resolver: bool,
+    #[cfg(feature = "debug-interp")]
+    debug_server: (Vec<c_char>, i32),
+    #[cfg(feature = "linux-perf")]
+    enable_linux_perf: bool,
+}
+
+impl RuntimeConfig {
+    #[allow(non_upper_case_globals)]
+    fn new(builder: &RuntimeBuilder) -> Self {
+        let args = &builder.args;
+        // the default running mode is the best one compiled in, as WAMR picks
+        let running_mode = match args.running_mode {
+            RunningMode_Mode_Default => [
+                RunningMode::MultiTierJit,
+                RunningMode::LlvmJit,
+                RunningMode::FastJit,
+            ]
+            .into_iter()
+            .find(|mode| mode.is_compiled_in())
+            .unwrap_or(RunningMode::Interp)
+            .into(),
+            running_mode => running_mode,
+        };
+        let pool_size = match args.mem_alloc_type {
+            mem_alloc_type_t_Alloc_With_Pool => unsafe { args.mem_alloc_option.pool.heap_size },
+            _ => 0,
+        };
 
+        RuntimeConfig {
+            mem_alloc_type: args.mem_alloc_type,
+            pool_size,
+            allocator: builder
+                .allocator
+                .map(|allocator| allocator as *const _ as *const () as usize)
+                .unwrap_or_default(),
+            running_mode,
+            llvm_jit_opt_level: args.llvm_jit_opt_level,
+            llvm_jit_size_level: args.llvm_jit_size_level,
+            segue_flags: args.segue_flags,
+            max_thread_num: args.max_thread_num,
+            fast_jit_code_cache_size: args.fast_jit_code_cache_size,
+            gc_heap_size: args.gc_heap_size,
+            host_module_name: builder
+                .host_functions
+                .module_name
+                .to_string_lossy()
+                .into_owned(),
+            host_functions: builder.host_functions.get_function_ptrs(),
+            // closures can't be compared, a builder with one never shares a runtime.
+            // see `Runtime::get_or_init()`
+            import_resolver: builder.import_resolver.is_some(),
+            #[cfg(feature = "debug-interp")]
+            debug_server: (args.ip_addr.to_vec(), args.instance_port),
+            #[cfg(feature = "linux-perf")]
+            enable_linux_perf: args.enable_linux_perf,
+        }
+    }
+}
+
+/// the state of the WAMR runtime, shared by all `Runtime` handles
 #[allow(dead_code)]
 #[derive(Debug)]
-pub struct Runtime {
+struct RuntimeState {
+    config: RuntimeConfig,
+    // WAMR refers to the native symbols until destroyed
     host_functions: HostFunctionList,
+    import_resolver: Option<ImportResolver>,
+    // host functions created by `import_resolver`. WAMR refers to them until destroyed
+    resolved_host_functions: Mutex<Vec<RawHostFunctionList>>,
+    // the heap of WAMR with `RuntimeBuilder::use_memory_pool()`, freed after destroyed
+    pool: Option<Vec<u8>>,
+    // the `proc_exit` of the SDK. WAMR refers to it until destroyed
+    proc_exit: HostFunctionList,
+}
+
+// the host function list isn't changed after the initialization, the rest is `Sync`
+unsafe impl Send for RuntimeState {}
+unsafe impl Sync for RuntimeState {}
+
+// the live runtime of the process, if any
+static RUNTIME: Mutex<Option<Arc<RuntimeState>>> = Mutex::new(None);
+
+/// A handle of the process-wide WAMR runtime.
+/// The runtime is destroyed when the last handle, including clones, is dropped
+#[derive(Debug)]
+pub struct Runtime {
+    state: Arc<RuntimeState>,
 }
 
 impl Runtime {
@@ -37,23 +320,200 @@ impl Runtime {
     /// - system allocator mode
     /// - the default running mode
     ///
+    /// if the runtime is alive, return a handle of it, whatever its configuration is
+    ///
     /// # Errors
     ///
     /// if the runtime initialization failed, it will return `RuntimeError::InitializationFailure`
     pub fn new() -> Result<Self, RuntimeError> {
-        match unsafe { wasm_runtime_init() } {
-            true => Ok(Runtime {
-                host_functions: HostFunctionList::new("empty"),
+        Self::get_or_init(None, || {
+            match unsafe { wasm_runtime_init() } {
+                true => Ok(RuntimeState {
+                    // as wasm_runtime_init() does
+                    config: RuntimeConfig::new(&Runtime::builder().use_system_allocator()),
+                    host_functions: HostFunctionList::new("empty"),
+                    import_resolver: None,
+                    resolved_host_functions: Mutex::new(Vec::new()),
+                    pool: None,
+                    proc_exit: proc_exit::host_functions(),
+                }),
+                false => Err(RuntimeError::InitializationFailure),
+            }
+        })
+    }
+
+    /// return a handle of the live runtime, or initialize one by `init`.
+    /// `config` is what the caller requires, `None` accepts any
+    fn get_or_init<F>(config: Option<&RuntimeConfig>, init: F) -> Result<Self, RuntimeError>
+    where
+        F: FnOnce() -> Result<RuntimeState, RuntimeError>,
+    {
+        let mut live = RUNTIME.lock().unwrap();
+
+        if let Some(state) = live.as_ref() {
+            return match config {
+                // its closures would be dropped, the imports resolved by the live ones
+                Some(config) if config.import_resolver => {
+                    Err(RuntimeError::ConflictingConfiguration(String::from(
+                        "a builder with an import resolver can't share the live runtime",
+                    )))
+                }
+                Some(config) if *config != state.config => {
+                    Err(RuntimeError::ConflictingConfiguration(format!(
+                        "the live runtime has {:?}, but {:?} is requested",
+                        state.config, config
+                    )))
+                }
+                _ => Ok(Runtime {
+                    state: state.clone(),
+                }),
+            };
+        }
+
+        #[cfg(feature = "log-hook")]
+        crate::log_hook::install();
+
+        let mut state = init()?;
+        if !proc_exit::register(&mut state.proc_exit) {
+            unsafe { wasm_runtime_destroy() };
+            return Err(RuntimeError::InitializationFailure);
+        }
+
+        let state = Arc::new(state);
+        *live = Some(state.clone());
+        Ok(Runtime { state })
+    }
+
+    /// set the log level of WAMR, it is process-wide.
+    ///
+    /// with the `log` feature, WAMR logs are `log` records of the target "wamr", with
+    /// the level WAMR logs them at. `LogLevel::Debug` and `LogLevel::Verbose` take effect
+    /// only with a debug build of WAMR
+    pub fn set_log_level(&self, level: LogLevel) {
+        unsafe { wasm_runtime_set_log_level(level.into()) };
+        // the log callback of the log hook isn't filtered by WAMR
+        #[cfg(feature = "log-hook")]
+        crate::log_hook::set_level(level.into());
+    }
+
+    /// whether instances can run in `mode`
+    pub fn is_running_mode_supported(&self, mode: RunningMode) -> bool {
+        unsafe { wasm_runtime_is_running_mode_supported(mode.into()) }
+    }
+
+    /// the WAMR build features compiled in. JIT flavours are asked of WAMR,
+    /// the rest is the build configuration exported by wamr-sys
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            interp: self.is_running_mode_supported(RunningMode::Interp),
+            fast_jit: self.is_running_mode_supported(RunningMode::FastJit),
+            llvm_jit: self.is_running_mode_supported(RunningMode::LlvmJit),
+            multi_tier_jit: self.is_running_mode_supported(RunningMode::MultiTierJit),
+            default_running_mode: RunningMode::from_raw(self.state.config.running_mode)
+                .unwrap_or(RunningMode::Interp),
+            simd: WAMR_BUILD_SIMD,
+            threads: WAMR_BUILD_SHARED_MEMORY,
+            gc: WAMR_BUILD_GC,
+            memory64: WAMR_BUILD_MEMORY64,
+            debug: WAMR_BUILD_DEBUG_INTERP,
+            perf_profiling: WAMR_BUILD_PERF_PROFILING,
+            memory_profiling: WAMR_BUILD_MEMORY_PROFILING,
+        }
+    }
+
+    /// the usage of the memory pool set by `RuntimeBuilder::use_memory_pool()`.
+    /// `None` with other allocation modes
+    pub fn mem_alloc_info(&self) -> Option<MemAllocInfo> {
+        let mut info = mem_alloc_info_t::default();
+        match unsafe { wasm_runtime_get_mem_alloc_info(&mut info) } {
+            true => Some(MemAllocInfo {
+                total_size: info.total_size,
+                total_free_size: info.total_free_size,
+                highmark_size: info.highmark_size,
             }),
-            false => Err(RuntimeError::InitializationFailure),
+            false => None,
+        }
+    }
+
+    /// offer every unlinked function import of a freshly loaded module to the
+    /// import resolver, register what it provides and link them to the module
+    pub(crate) fn resolve_imports(&self, module: wasm_module_t) -> Result<(), RuntimeError> {
+        let resolver = match &self.state.import_resolver {
+            Some(resolver) => resolver,
+            None => return Ok(()),
+        };
+
+        let mut resolved: BTreeMap<String, RawHostFunctionList> = BTreeMap::new();
+        for import in Module::get_raw_module_imports(module) {
+            let func_type = match &import.ty {
+                ExternType::Func(func_type) if !import.linked => func_type,
+                _ => continue,
+            };
+
+            if !ImportResolver::is_resolvable(func_type) {
+                continue;
+            }
+
+            if let Some(host_func) = resolver.resolve(&import.module_name, &import.name, func_type)
+            {
+                resolved
+                    .entry(import.module_name.clone())
+                    .or_insert_with(|| RawHostFunctionList::new(&import.module_name))
+                    .register_host_function(&import.name, func_type, host_func);
+            }
+        }
+
+        if resolved.is_empty() {
+            return Ok(());
+        }
+
+        let mut resolved_host_functions = self.state.resolved_host_functions.lock().unwrap();
+        for (module_name, mut host_functions) in resolved {
+            let registered = unsafe {
+                let module_name_ptr = host_functions.get_module_name().as_ptr();
+                let native_symbols = host_functions.get_native_symbols();
+                wasm_runtime_register_natives_raw(
+                    module_name_ptr,
+                    native_symbols.as_mut_ptr(),
+                    native_symbols.len() as u32,
+                )
+            };
+            if !registered {
+                return Err(RuntimeError::CompilationError(format!(
+                    "failed to register host functions of {}",
+                    module_name
+                )));
+            }
+
+            resolved_host_functions.push(host_functions);
+        }
+
+        // some imports may stay unlinked. it is reported when calling them
+        unsafe { wasm_runtime_resolve_symbols(module) };
+        Ok(())
+    }
+}
+
+impl Clone for Runtime {
+    fn clone(&self) -> Self {
+        // in step with `drop()`
+        let _live = RUNTIME.lock().unwrap();
+        Runtime {
+            state: self.state.clone(),
         }
     }
 }
 
 impl Drop for Runtime {
     fn drop(&mut self) {
-        unsafe {
-            wasm_runtime_destroy();
+        let mut live = RUNTIME.lock().unwrap();
+
+        // this one and the one in RUNTIME
+        if Arc::strong_count(&self.state) == 2 {
+            live.take();
+            unsafe {
+                wasm_runtime_destroy();
+            }
         }
     }
 }
@@ -63,6 +523,9 @@ impl Drop for Runtime {
 pub struct RuntimeBuilder {
     args: RuntimeInitArgs,
     host_functions: HostFunctionList,
+    import_resolver: Option<ImportResolver>,
+    pool: Option<Vec<u8>>,
+    allocator: Option<&'static (dyn GlobalAlloc + Sync)>,
 }
 
 /// Can't build() until config allocator mode
@@ -72,6 +535,9 @@ impl Default for RuntimeBuilder {
         RuntimeBuilder {
             args,
             host_functions: HostFunctionList::new("host"),
+            import_resolver: None,
+            pool: None,
+            allocator: None,
         }
     }
 }
@@ -81,15 +547,34 @@ impl RuntimeBuilder {
     /// allocate memory from system allocator for runtime consumed memory
     pub fn use_system_allocator(mut self) -> RuntimeBuilder {
         self.args.mem_alloc_type = mem_alloc_type_t_Alloc_With_System_Allocator;
+        self.pool = None;
+        self.allocator = None;
         self
     }
 
     /// system allocator mode
-    /// allocate memory from pool, as a pre-allocated buffer, for runtime consumed memory
+    /// allocate memory from pool, as a pre-allocated buffer, for runtime consumed memory.
+    /// the runtime owns the pool until it is destroyed
     pub fn use_memory_pool(mut self, mut pool: Vec<u8>) -> RuntimeBuilder {
         self.args.mem_alloc_type = mem_alloc_type_t_Alloc_With_Pool;
+        // the buffer doesn't move with the `Vec`
         self.args.mem_alloc_option.pool.heap_buf = pool.as_mut_ptr() as *mut c_void;
         self.args.mem_alloc_option.pool.heap_size = pool.len() as u32;
+        self.pool = Some(pool);
+        self.allocator = None;
+        self
+    }
+
+    /// allocator mode
+    /// allocate memory from `allocator` for runtime consumed memory, to track or
+    /// cap it. linear memories may be allocated by mmap() instead, depends on the platform
+    pub fn use_allocator(mut self, allocator: &'static (dyn GlobalAlloc + Sync)) -> RuntimeBuilder {
+        self.args.mem_alloc_type = mem_alloc_type_t_Alloc_With_Allocator;
+        self.args.mem_alloc_option.allocator.malloc_func = allocator::malloc as *mut c_void;
+        self.args.mem_alloc_option.allocator.realloc_func = allocator::realloc as *mut c_void;
+        self.args.mem_alloc_option.allocator.free_func = allocator::free as *mut c_void;
+        self.pool = None;
+        self.allocator = Some(allocator);
         self
     }
 
@@ -99,7 +584,8 @@ impl RuntimeBuilder {
         self
     }
 
-    /// use llvm-jit mode
+    /// use llvm-jit mode. the levels are passed to WAMR as they are,
+    /// see `try_run_as_llvm_jit()` to check them
     pub fn run_as_llvm_jit(mut self, opt_level: u32, size_level: u32) -> RuntimeBuilder {
         self.args.running_mode = RunningMode_Mode_LLVM_JIT;
         self.args.llvm_jit_opt_level = opt_level;
@@ -107,6 +593,176 @@ impl RuntimeBuilder {
         self
     }
 
+    /// use llvm-jit mode
+    ///
+    /// # Errors
+    ///
+    /// without the `llvmjit` feature, it will return `RuntimeError::FeatureNotEnabled`.
+    /// if a level is out of 0 to 3, it will return `RuntimeError::InvalidConfiguration`
+    pub fn try_run_as_llvm_jit(
+        self,
+        opt_level: u32,
+        size_level: u32,
+    ) -> Result<RuntimeBuilder, RuntimeError> {
+        let opt_level = check_llvm_jit_level("opt level", opt_level)?;
+        let size_level = check_llvm_jit_level("size level", size_level)?;
+        Ok(self.run_as_llvm_jit(opt_level, size_level))
+    }
+
+    /// use fast-jit mode. it compiles quickly without LLVM, x86-64 only
+    #[cfg(feature = "fast-jit")]
+    pub fn run_as_fast_jit(mut self) -> RuntimeBuilder {
+        self.args.running_mode = RunningMode_Mode_Fast_JIT;
+        self
+    }
+
+    /// use multi-tier-jit mode. functions start in fast-jit, and hot ones are
+    /// recompiled by llvm-jit in the background. WAMR builds it whenever both are
+    ///
+    /// # Errors
+    ///
+    /// if a level is out of 0 to 3, it will return `RuntimeError::InvalidConfiguration`
+    #[cfg(all(feature = "fast-jit", feature = "llvmjit"))]
+    pub fn run_as_multi_tier_jit(
+        mut self,
+        opt_level: u32,
+        size_level: u32,
+    ) -> Result<RuntimeBuilder, RuntimeError> {
+        self.args.running_mode = RunningMode_Mode_Multi_Tier_JIT;
+        self.args.llvm_jit_opt_level = check_llvm_jit_level("opt level", opt_level)?;
+        self.args.llvm_jit_size_level = check_llvm_jit_level("size level", size_level)?;
+        Ok(self)
+    }
+
+    /// use `mode`
+    ///
+    /// # Errors
+    ///
+    /// if the mode isn't compiled in, it will return `RuntimeError::FeatureNotEnabled`
+    pub fn running_mode(mut self, mode: RunningMode) -> Result<RuntimeBuilder, RuntimeError> {
+        if !mode.is_compiled_in() {
+            return Err(RuntimeError::FeatureNotEnabled(mode.feature()));
+        }
+        self.args.running_mode = mode.into();
+        Ok(self)
+    }
+
+    /// the optimization level of LLVM JIT, 0 to 3
+    ///
+    /// # Errors
+    ///
+    /// without the `llvmjit` feature, it will return `RuntimeError::FeatureNotEnabled`.
+    /// if the level is out of range, it will return `RuntimeError::InvalidConfiguration`
+    pub fn llvm_jit_opt_level(mut self, level: u32) -> Result<RuntimeBuilder, RuntimeError> {
+        self.args.llvm_jit_opt_level = check_llvm_jit_level("opt level", level)?;
+        Ok(self)
+    }
+
+    /// the code size level of LLVM JIT, 0 to 3
+    ///
+    /// # Errors
+    ///
+    /// without the `llvmjit` feature, it will return `RuntimeError::FeatureNotEnabled`.
+    /// if the level is out of range, it will return `RuntimeError::InvalidConfiguration`
+    pub fn llvm_jit_size_level(mut self, level: u32) -> Result<RuntimeBuilder, RuntimeError> {
+        self.args.llvm_jit_size_level = check_llvm_jit_level("size level", level)?;
+        Ok(self)
+    }
+
+    /// the memory accesses LLVM JIT optimizes with the segment register
+    ///
+    /// # Errors
+    ///
+    /// without the `llvmjit` feature, it will return `RuntimeError::FeatureNotEnabled`
+    pub fn segue_flags(mut self, flags: SegueFlags) -> Result<RuntimeBuilder, RuntimeError> {
+        if !cfg!(feature = "llvmjit") {
+            return Err(RuntimeError::FeatureNotEnabled("llvmjit"));
+        }
+        self.args.segue_flags = flags.bits();
+        Ok(self)
+    }
+
+    /// the code cache size of Fast JIT, in bytes. 0 is the default of WAMR
+    ///
+    /// # Errors
+    ///
+    /// without the `fast-jit` feature, it will return `RuntimeError::FeatureNotEnabled`
+    pub fn fast_jit_code_cache_size(mut self, size: u32) -> Result<RuntimeBuilder, RuntimeError> {
+        if !RunningMode::FastJit.is_compiled_in() {
+            return Err(RuntimeError::FeatureNotEnabled(
+                RunningMode::FastJit.feature(),
+            ));
+        }
+        self.args.fast_jit_code_cache_size = size;
+        Ok(self)
+    }
+
+    /// the heap size of garbage collected objects, in bytes. 0 is the default of WAMR
+    ///
+    /// # Errors
+    ///
+    /// without the `gc` feature, it will return `RuntimeError::FeatureNotEnabled`
+    pub fn gc_heap_size(mut self, size: u32) -> Result<RuntimeBuilder, RuntimeError> {
+        if !WAMR_BUILD_GC {
+            return Err(RuntimeError::FeatureNotEnabled("gc"));
+        }
+        self.args.gc_heap_size = size;
+        Ok(self)
+    }
+
+    /// the max number of threads which run wasm at the same time, the ones
+    /// spawned by wasi-threads
+    ///
+    /// # Errors
+    ///
+    /// without the `wasi-threads` feature, it will return `RuntimeError::FeatureNotEnabled`.
+    /// if `num` is 0, it will return `RuntimeError::InvalidConfiguration`
+    pub fn max_thread_num(mut self, num: u32) -> Result<RuntimeBuilder, RuntimeError> {
+        if !WAMR_BUILD_LIB_WASI_THREADS {
+            return Err(RuntimeError::FeatureNotEnabled("wasi-threads"));
+        }
+        if num == 0 {
+            return Err(RuntimeError::InvalidConfiguration(String::from(
+                "max thread num must be positive",
+            )));
+        }
+        self.args.max_thread_num = num;
+        Ok(self)
+    }
+
+    /// listen for lldb on `ip`. the debug server of the first instance started by
+    /// `Instance::start_debug_server()` uses `port`, the next one `port + 1`, and so on
+    ///
+    /// if `ip` doesn't fit in 127 bytes or contains a NUL, it will return
+    /// `RuntimeError::InvalidConfiguration`
+    #[cfg(feature = "debug-interp")]
+    pub fn debug_server(mut self, ip: &str, port: u16) -> Result<RuntimeBuilder, RuntimeError> {
+        // keep the trailing NUL
+        if ip.len() >= self.args.ip_addr.len() || ip.contains('\0') {
+            return Err(RuntimeError::InvalidConfiguration(format!(
+                "debug server ip {:?} isn't a valid address of at most {} bytes",
+                ip,
+                self.args.ip_addr.len() - 1
+            )));
+        }
+
+        self.args.ip_addr = [0; 128];
+        for (dst, src) in self.args.ip_addr.iter_mut().zip(ip.as_bytes()) {
+            *dst = *src as c_char;
+        }
+        self.args.instance_port = port as i32;
+        Ok(self)
+    }
+
+    /// write a perf map of JIT and AOT functions to /tmp/perf-<pid>.map,
+    /// so `perf report` shows guest function names instead of anonymous addresses.
+    /// it is off by default
+    #[cfg(feature = "linux-perf")]
+    pub fn enable_linux_perf(mut self, enable: bool) -> RuntimeBuilder {
+        self.args.enable_linux_perf = enable;
+        self
+    }
+
     /// register a host function
     pub fn register_host_function(
         mut self,
@@ -118,90 +774,300 @@ impl RuntimeBuilder {
         self
     }
 
-    /// create a `Runtime` instance with the configuration
+    /// set a callback to provide host functions on demand
+    ///
+    /// When a module is loaded, the resolver is called with the module name, the name
+    /// and the type of every function import which isn't linked yet. The `HostFunc`
+    /// returned is registered as a raw native under the module name, so it is shared
+    /// by all modules loaded later. Return `None` to leave the import unlinked.
+    ///
+    /// Only functions with `i32`, `i64`, `f32` and `f64` parameters and at most one
+    /// result are offered to the resolver.
+    pub fn import_resolver<F>(mut self, resolver: F) -> RuntimeBuilder
+    where
+        F: Fn(&str, &str, &FuncType) -> Option<HostFunc> + Send + Sync + 'static,
+    {
+        self.import_resolver = Some(ImportResolver::new(resolver));
+        self
+    }
+
+    /// create a `Runtime` instance with the configuration.
+    /// if the runtime is alive, return a handle of it when the configuration is the same
     ///
     /// # Errors
     ///
     /// if the runtime initialization failed, it will return `RuntimeError::InitializationFailure`
+    /// if the live runtime has another configuration, it will return
+    /// `RuntimeError::ConflictingConfiguration`. a builder with an import resolver
+    /// always conflicts with a live runtime
     pub fn build(mut self) -> Result<Runtime, RuntimeError> {
-        match unsafe {
-            let module_name = &(self.host_functions).get_module_name();
-            self.args.native_module_name = module_name.as_ptr();
-
-            let native_symbols = &(self.host_functions).get_native_symbols();
-            self.args.n_native_symbols = native_symbols.len() as u32;
-            self.args.native_symbols = native_symbols.as_ptr() as *mut NativeSymbol;
-
-            wasm_runtime_full_init(&mut self.args)
-        } {
-            true => Ok(Runtime {
-                host_functions: self.host_functions,
-            }),
-            false => Err(RuntimeError::InitializationFailure),
-        }
+        let config = RuntimeConfig::new(&self);
+
+        Runtime::get_or_init(Some(&config), || {
+            if let Some(allocator) = self.allocator {
+                allocator::set_allocator(allocator);
+            }
+
+            match unsafe {
+                let module_name = &(self.host_functions).get_module_name();
+                self.args.native_module_name = module_name.as_ptr();
+
+                let native_symbols = &(self.host_functions).get_native_symbols();
+                self.args.n_native_symbols = native_symbols.len() as u32;
+                self.args.native_symbols = native_symbols.as_ptr() as *mut NativeSymbol;
+
+                wasm_runtime_full_init(&mut self.args)
+            } {
+                true => Ok(RuntimeState {
+                    config: config.clone(),
+                    host_functions: self.host_functions,
+                    import_resolver: self.import_resolver,
+                    resolved_host_functions: Mutex::new(Vec::new()),
+                    pool: self.pool,
+                    proc_exit: proc_exit::host_functions(),
+                }),
+                false => Err(RuntimeError::InitializationFailure),
+            }
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "fast-jit")]
+    use crate::{function::Function, instance::Instance, module::Module, value::WasmValue};
     use wamr_sys::{wasm_runtime_free, wasm_runtime_malloc};
 
     #[test]
     #[ignore]
-    fn test_runtime_new() {
-        let runtime = Runtime::new();
-        assert!(runtime.is_ok());
+    fn test_runtime_builder_memory_pool() {
+        let runtime = Runtime::builder()
+            .use_memory_pool(vec![0u8; 1024 * 1024])
+            .build()
+            .unwrap();
 
-        /* use malloc to confirm */
         let small_buf = unsafe { wasm_runtime_malloc(16) };
         assert!(!small_buf.is_null());
-        unsafe { wasm_runtime_free(small_buf) };
 
-        drop(runtime);
+        let info = runtime.mem_alloc_info().unwrap();
+        assert!(info.total_size <= 1024 * 1024);
+        assert!(info.total_free_size < info.total_size);
+        unsafe { wasm_runtime_free(small_buf) };
+    }
 
-        /* runtime has been destroyed. malloc should be failed */
-        let small_buf = unsafe { wasm_runtime_malloc(16) };
-        assert!(small_buf.is_null());
+    #[test]
+    #[ignore]
+    fn test_runtime_builder_allocator() {
+        use std::{
+            alloc::{Layout, System},
+            sync::atomic::{AtomicUsize, Ordering},
+        };
 
-        {
-            let runtime = Runtime::new();
-            assert!(runtime.is_ok());
+        struct Counter(AtomicUsize);
 
-            let runtime = Runtime::new();
-            assert!(runtime.is_ok());
+        unsafe impl GlobalAlloc for Counter {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                self.0.fetch_add(layout.size(), Ordering::SeqCst);
+                System.alloc(layout)
+            }
 
-            let runtime = Runtime::new();
-            assert!(runtime.is_ok());
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                self.0.fetch_sub(layout.size(), Ordering::SeqCst);
+                System.dealloc(ptr, layout)
+            }
         }
 
-        /* runtime has been destroyed. malloc should be failed */
-        let small_buf = unsafe { wasm_runtime_malloc(16) };
-        assert!(small_buf.is_null());
-    }
+        static COUNTER: Counter = Counter(AtomicUsize::new(0));
 
-    #[test]
-    fn test_runtime_builder_default() {
-        // use Mode_Default
-        let runtime = Runtime::builder().use_system_allocator().build();
+        let runtime = Runtime::builder().use_allocator(&COUNTER).build();
         assert!(runtime.is_ok());
+        let in_use = COUNTER.0.load(Ordering::SeqCst);
 
         let small_buf = unsafe { wasm_runtime_malloc(16) };
         assert!(!small_buf.is_null());
+        assert!(COUNTER.0.load(Ordering::SeqCst) > in_use);
         unsafe { wasm_runtime_free(small_buf) };
+        assert_eq!(COUNTER.0.load(Ordering::SeqCst), in_use);
     }
 
     #[test]
-    fn test_runtime_builder_interpreter() {
-        let runtime = Runtime::builder()
-            .run_as_interpreter()
+    fn test_runtime_builder_typed_setters() {
+        let builder = Runtime::builder()
+            .running_mode(RunningMode::Interp)
+            .unwrap();
+        assert_eq!(builder.args.running_mode, RunningMode_Mode_Interp);
+
+        let threads = Runtime::builder().max_thread_num(8);
+        if cfg!(feature = "wasi-threads") {
+            assert_eq!(threads.unwrap().args.max_thread_num, 8);
+            assert!(matches!(
+                Runtime::builder().max_thread_num(0),
+                Err(RuntimeError::InvalidConfiguration(_))
+            ));
+        } else {
+            assert!(matches!(
+                threads,
+                Err(RuntimeError::FeatureNotEnabled("wasi-threads"))
+            ));
+        }
+
+        let gc = Runtime::builder().gc_heap_size(1024 * 1024);
+        if cfg!(feature = "gc") {
+            assert_eq!(gc.unwrap().args.gc_heap_size, 1024 * 1024);
+        } else {
+            assert!(matches!(gc, Err(RuntimeError::FeatureNotEnabled("gc"))));
+        }
+
+        let llvm_jit = Runtime::builder().running_mode(RunningMode::LlvmJit);
+        if cfg!(feature = "llvmjit") {
+            let builder = llvm_jit
+                .unwrap()
+                .llvm_jit_opt_level(2)
+                .unwrap()
+                .llvm_jit_size_level(1)
+                .unwrap()
+                .segue_flags(SegueFlags::I32_LOAD | SegueFlags::I32_STORE)
+                .unwrap();
+            assert_eq!(builder.args.llvm_jit_opt_level, 2);
+            assert_eq!(builder.args.llvm_jit_size_level, 1);
+            assert_eq!(builder.args.segue_flags, 0x101);
+            assert!(matches!(
+                Runtime::builder().llvm_jit_opt_level(4),
+                Err(RuntimeError::InvalidConfiguration(_))
+            ));
+            assert!(matches!(
+                Runtime::builder().try_run_as_llvm_jit(3, 4),
+                Err(RuntimeError::InvalidConfiguration(_))
+            ));
+            let builder = Runtime::builder().try_run_as_llvm_jit(2, 1).unwrap();
+            assert_eq!(builder.args.running_mode, RunningMode_Mode_LLVM_JIT);
+        } else {
+            assert!(matches!(
+                llvm_jit,
+                Err(RuntimeError::FeatureNotEnabled("llvmjit"))
+            ));
+            assert!(matches!(
+                Runtime::builder().segue_flags(SegueFlags::ALL),
+                Err(RuntimeError::FeatureNotEnabled("llvmjit"))
+            ));
+        }
+
+        let fast_jit = Runtime::builder().fast_jit_code_cache_size(1024 * 1024);
+        if cfg!(feature = "fast-jit") {
+            assert_eq!(fast_jit.unwrap().args.fast_jit_code_cache_size, 1024 * 1024);
+        } else {
+            assert!(matches!(
+                fast_jit,
+                Err(RuntimeError::FeatureNotEnabled("fast-jit"))
+            ));
+        }
+        assert_eq!(
+            Runtime::builder()
+                .running_mode(RunningMode::MultiTierJit)
+                .is_ok(),
+            cfg!(all(feature = "fast-jit", feature = "llvmjit"))
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn test_runtime_capabilities() {
+        let runtime = Runtime::new().unwrap();
+        let capabilities = runtime.capabilities();
+
+        assert!(capabilities.interp);
+        assert_eq!(capabilities.llvm_jit, cfg!(feature = "llvmjit"));
+        assert_eq!(capabilities.fast_jit, cfg!(feature = "fast-jit"));
+        assert!(capabilities.simd);
+        assert_eq!(capabilities.debug, cfg!(feature = "debug-interp"));
+        assert_eq!(
+            capabilities.memory_profiling,
+            cfg!(feature = "memory-profiling")
+        );
+        assert!(runtime.is_running_mode_supported(capabilities.default_running_mode));
+        for mode in [
+            RunningMode::Interp,
+            RunningMode::FastJit,
+            RunningMode::LlvmJit,
+            RunningMode::MultiTierJit,
+        ] {
+            assert_eq!(
+                runtime.is_running_mode_supported(mode),
+                mode.is_compiled_in()
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_runtime_set_log_level() {
+        let runtime = Runtime::new().unwrap();
+        runtime.set_log_level(LogLevel::Error);
+        runtime.set_log_level(LogLevel::Warning);
+
+        assert!(LogLevel::Fatal < LogLevel::Verbose);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_runtime_mem_alloc_info_system_allocator() {
+        let runtime = Runtime::builder().use_system_allocator().build().unwrap();
+        assert_eq!(runtime.mem_alloc_info(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-interp")]
+    fn test_runtime_builder_debug_server_args() {
+        let builder = Runtime::builder()
             .use_system_allocator()
-            .build();
+            .debug_server("127.0.0.1", 1234)
+            .unwrap();
+        assert_eq!(builder.args.instance_port, 1234);
+        assert_eq!(
+            builder.args.ip_addr[..10],
+            [49, 50, 55, 46, 48, 46, 48, 46, 49, 0]
+        );
+
+        let longest = "1".repeat(127);
+        let builder = Runtime::builder().debug_server(&longest, 1234).unwrap();
+        assert_eq!(builder.args.ip_addr[126], 49);
+        assert_eq!(builder.args.ip_addr[127], 0);
+
+        assert!(matches!(
+            Runtime::builder().debug_server(&"1".repeat(128), 1234),
+            Err(RuntimeError::InvalidConfiguration(_))
+        ));
+        assert!(matches!(
+            Runtime::builder().debug_server("127.0.0.1\0", 1234),
+            Err(RuntimeError::InvalidConfiguration(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "debug-interp")]
+    #[ignore]
+    fn test_runtime_builder_debug_server() {
+        let builder = Runtime::builder()
+            .use_system_allocator()
+            .debug_server("127.0.0.1", 1234)
+            .unwrap();
+
+        let runtime = builder.build();
         assert!(runtime.is_ok());
+    }
 
-        let small_buf = unsafe { wasm_runtime_malloc(16) };
-        assert!(!small_buf.is_null());
-        unsafe { wasm_runtime_free(small_buf) };
+    #[test]
+    #[cfg(feature = "linux-perf")]
+    #[ignore]
+    fn test_runtime_builder_linux_perf() {
+        let builder = Runtime::builder()
+            .use_system_allocator()
+            .enable_linux_perf(true);
+        assert!(builder.args.enable_linux_perf);
+
+        let runtime = builder.build();
+        assert!(runtime.is_ok());
     }
 
     #[test]
@@ -218,4 +1084,50 @@ mod tests {
         assert!(!small_buf.is_null());
         unsafe { wasm_runtime_free(small_buf) };
     }
+
+    #[test]
+    #[cfg(feature = "fast-jit")]
+    #[ignore]
+    fn test_runtime_builder_fast_jit() {
+        let runtime = Runtime::builder()
+            .run_as_fast_jit()
+            .use_system_allocator()
+            .build();
+        assert!(runtime.is_ok());
+        let runtime = runtime.unwrap();
+
+        // (module
+        //   (func (export "add") (param i32 i32) (result i32)
+        //     (i32.add (local.get 0) (local.get 1))
+        //   )
+        // )
+        let binary = vec![
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7F,
+            0x7F, 0x01, 0x7F, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+            0x00, 0x00, 0x0A, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6A, 0x0B,
+        ];
+        let module = Module::from_vec(&runtime, binary, "add");
+        assert!(module.is_ok());
+        let module = module.unwrap();
+
+        let instance = Instance::new(&runtime, &module, 1024);
+        assert!(instance.is_ok());
+        let instance = instance.unwrap();
+
+        let function = Function::find_export_func(&instance, "add").unwrap();
+        let result = function.call(&instance, &vec![WasmValue::I32(3), WasmValue::I32(4)]);
+        assert_eq!(result.unwrap(), vec![WasmValue::I32(7)]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "fast-jit", feature = "llvmjit"))]
+    #[ignore]
+    fn test_runtime_builder_multi_tier_jit() {
+        let runtime = Runtime::builder()
+            .run_as_multi_tier_jit(3, 3)
+            .unwrap()
+            .use_system_allocator()
+            .build();
+        assert!(runtime.is_ok());
+    }
 }
@@ -0,0 +1,42 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! `tracing` spans of loading, instantiation, calls and host calls.
+//! every span has `duration_us`. a failed one has `trap`, `exit_code` or `error`
+
+use std::time::Instant;
+
+use tracing::{field::display, Span};
+
+use crate::{trap::Trap, value::WasmValue, RuntimeError};
+
+/// record how long the span took and how it failed, if it did
+pub(crate) fn finish<T>(span: &Span, start: Instant, result: &Result<T, RuntimeError>) {
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+
+    let error = match result {
+        Ok(_) => return,
+        Err(error) => error,
+    };
+
+    match error {
+        RuntimeError::ExecutionError(info) => span.record("trap", display(info.trap)),
+        RuntimeError::Terminated => span.record("trap", display(Trap::Terminated)),
+        RuntimeError::Timeout => span.record("trap", "timeout"),
+        RuntimeError::OutOfFuel => span.record("trap", display(Trap::OutOfFuel)),
+        RuntimeError::Exit(code) => span.record("exit_code", code),
+        other => span.record("error", display(other)),
+    };
+}
+
+/// like `finish()`, for a `HostFunc` which returns the message of its trap
+pub(crate) fn finish_host_call(span: &Span, start: Instant, result: &Result<WasmValue, String>) {
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+
+    if let Err(message) = result {
+        span.record("trap", display(Trap::HostTrap));
+        span.record("error", message.as_str());
+    }
+}
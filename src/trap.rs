@@ -0,0 +1,160 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! the kind of a trap, classified from the exception raised by WAMR
+
+use std::fmt;
+
+/// the prefix of exceptions raised by host functions which return `Err`
+pub(crate) const HOST_TRAP_PREFIX: &str = "host trap: ";
+
+/// Why a wasm function traps. Read it via `ExecError::trap`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Trap {
+    /// executed an `unreachable`
+    Unreachable,
+    /// accessed linear memory out of bounds
+    MemoryOutOfBounds,
+    /// accessed a table out of bounds
+    TableOutOfBounds,
+    /// signed integer division overflowed
+    IntegerOverflow,
+    /// integer division or remainder by zero
+    DivideByZero,
+    /// converted a NaN or out of range float to integer
+    BadConversionToInteger,
+    /// ran out of the wasm operand stack, the auxiliary stack or the native stack
+    StackOverflow,
+    /// `call_indirect` to a function of another type
+    IndirectCallTypeMismatch,
+    /// `call_indirect` to an index out of the table
+    UndefinedElement,
+    /// `call_indirect` to a null table element
+    UninitializedElement,
+    /// called an import which isn't linked
+    UnlinkedImport,
+    /// an atomic access isn't aligned
+    UnalignedAtomic,
+    /// the runtime failed to allocate memory
+    OutOfMemory,
+    /// a host function reported an error
+    HostTrap,
+    /// terminated by `TerminateHandle::terminate()` or a timeout
+    Terminated,
+    /// the instruction budget ran out
+    OutOfFuel,
+    /// WASI `proc_exit` was called
+    Exit,
+    /// an exception this SDK doesn't recognize
+    Unknown,
+}
+
+// (pattern, trap). patterns are matched in order as substrings
+const EXCEPTION_PATTERNS: &[(&str, Trap)] = &[
+    (HOST_TRAP_PREFIX, Trap::HostTrap),
+    ("unreachable", Trap::Unreachable),
+    ("out of bounds memory access", Trap::MemoryOutOfBounds),
+    ("out of bounds table access", Trap::TableOutOfBounds),
+    ("integer overflow", Trap::IntegerOverflow),
+    ("integer divide by zero", Trap::DivideByZero),
+    (
+        "invalid conversion to integer",
+        Trap::BadConversionToInteger,
+    ),
+    ("stack overflow", Trap::StackOverflow),
+    (
+        "indirect call type mismatch",
+        Trap::IndirectCallTypeMismatch,
+    ),
+    ("undefined element", Trap::UndefinedElement),
+    ("uninitialized element", Trap::UninitializedElement),
+    ("unlinked import function", Trap::UnlinkedImport),
+    ("unaligned atomic", Trap::UnalignedAtomic),
+    ("allocate memory failed", Trap::OutOfMemory),
+    ("terminated by user", Trap::Terminated),
+    ("instruction limit exceeded", Trap::OutOfFuel),
+    ("wasi proc exit", Trap::Exit),
+];
+
+impl Trap {
+    /// classify an exception message of WAMR, like "Exception: integer divide by zero"
+    pub fn from_exception(message: &str) -> Trap {
+        EXCEPTION_PATTERNS
+            .iter()
+            .find(|(pattern, _)| message.contains(pattern))
+            .map(|(_, trap)| *trap)
+            .unwrap_or(Trap::Unknown)
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Trap::Unreachable => "unreachable executed",
+            Trap::MemoryOutOfBounds => "out of bounds memory access",
+            Trap::TableOutOfBounds => "out of bounds table access",
+            Trap::IntegerOverflow => "integer overflow",
+            Trap::DivideByZero => "integer divide by zero",
+            Trap::BadConversionToInteger => "invalid conversion to integer",
+            Trap::StackOverflow => "stack overflow",
+            Trap::IndirectCallTypeMismatch => "indirect call type mismatch",
+            Trap::UndefinedElement => "undefined element",
+            Trap::UninitializedElement => "uninitialized element",
+            Trap::UnlinkedImport => "call to an unlinked import",
+            Trap::UnalignedAtomic => "unaligned atomic access",
+            Trap::OutOfMemory => "out of memory",
+            Trap::HostTrap => "host function trap",
+            Trap::Terminated => "terminated",
+            Trap::OutOfFuel => "out of fuel",
+            Trap::Exit => "wasi proc_exit",
+            Trap::Unknown => "unknown trap",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trap_from_exception() {
+        let cases = [
+            ("Exception: unreachable", Trap::Unreachable),
+            (
+                "Exception: out of bounds memory access",
+                Trap::MemoryOutOfBounds,
+            ),
+            ("Exception: integer overflow", Trap::IntegerOverflow),
+            ("Exception: integer divide by zero", Trap::DivideByZero),
+            (
+                "Exception: wasm operand stack overflow",
+                Trap::StackOverflow,
+            ),
+            ("Exception: native stack overflow", Trap::StackOverflow),
+            // a runtime bug rather than a stack exhausted by the program
+            ("Exception: wasm operand stack underflow", Trap::Unknown),
+            (
+                "Exception: indirect call type mismatch",
+                Trap::IndirectCallTypeMismatch,
+            ),
+            ("Exception: undefined element", Trap::UndefinedElement),
+            (
+                "Exception: failed to call unlinked import function (env, foo)",
+                Trap::UnlinkedImport,
+            ),
+            ("Exception: host trap: unreachable", Trap::HostTrap),
+            ("Exception: terminated by user", Trap::Terminated),
+            ("Exception: instruction limit exceeded", Trap::OutOfFuel),
+            ("Exception: wasi proc exit", Trap::Exit),
+            ("Exception: something else", Trap::Unknown),
+            ("", Trap::Unknown),
+        ];
+
+        for (message, trap) in cases {
+            assert_eq!(Trap::from_exception(message), trap, "{}", message);
+        }
+    }
+}
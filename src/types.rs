@@ -0,0 +1,164 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! descriptions of imports and exports of a module.
+//! get them via `Module::get_imports()`
+
+#![allow(non_upper_case_globals)]
+
+use wamr_sys::{
+    wasm_func_type_get_param_count, wasm_func_type_get_param_valkind,
+    wasm_func_type_get_result_count, wasm_func_type_get_result_valkind, wasm_func_type_t,
+    wasm_global_type_get_mutable, wasm_global_type_get_valkind, wasm_global_type_t,
+    wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_FUNC,
+    wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_GLOBAL,
+    wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_MEMORY,
+    wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_TABLE, wasm_import_t,
+    wasm_memory_type_get_init_page_count, wasm_memory_type_get_max_page_count,
+    wasm_memory_type_get_shared, wasm_memory_type_t, wasm_table_type_get_elem_kind,
+    wasm_table_type_get_init_size, wasm_table_type_get_max_size, wasm_table_type_t,
+};
+
+use crate::{helper::cstr_to_string, value::WasmValType};
+
+/// parameters and results of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<WasmValType>,
+    pub results: Vec<WasmValType>,
+}
+
+/// element type and limits of a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableType {
+    pub elem_type: WasmValType,
+    pub init_size: u32,
+    pub max_size: u32,
+}
+
+/// limits of a linear memory, in pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryType {
+    pub init_pages: u32,
+    pub max_pages: u32,
+    pub shared: bool,
+}
+
+/// value type and mutability of a global
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalType {
+    pub val_type: WasmValType,
+    pub mutable: bool,
+}
+
+/// the type of an imported or exported entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternType {
+    Func(FuncType),
+    Table(TableType),
+    Memory(MemoryType),
+    Global(GlobalType),
+    /// an import kind this SDK doesn't know about yet
+    Unsupported(u32),
+}
+
+/// an import of a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportType {
+    pub module_name: String,
+    pub name: String,
+    pub ty: ExternType,
+    /// whether the runtime has found an implementation for the import
+    pub linked: bool,
+}
+
+impl FuncType {
+    /// # Safety
+    ///
+    /// `func_type` must be a valid function type returned by WAMR
+    pub(crate) unsafe fn from_raw(func_type: wasm_func_type_t) -> Self {
+        let param_count = wasm_func_type_get_param_count(func_type);
+        let param_kind = |i| wasm_func_type_get_param_valkind(func_type, i) as u32;
+        let result_count = wasm_func_type_get_result_count(func_type);
+        let result_kind = |i| wasm_func_type_get_result_valkind(func_type, i) as u32;
+
+        FuncType {
+            params: (0..param_count)
+                .map(|i| WasmValType::from_valkind(param_kind(i)))
+                .collect(),
+            results: (0..result_count)
+                .map(|i| WasmValType::from_valkind(result_kind(i)))
+                .collect(),
+        }
+    }
+}
+
+impl TableType {
+    /// # Safety
+    ///
+    /// `table_type` must be a valid table type returned by WAMR
+    pub(crate) unsafe fn from_raw(table_type: wasm_table_type_t) -> Self {
+        TableType {
+            elem_type: WasmValType::from_valkind(wasm_table_type_get_elem_kind(table_type) as u32),
+            init_size: wasm_table_type_get_init_size(table_type),
+            max_size: wasm_table_type_get_max_size(table_type),
+        }
+    }
+}
+
+impl MemoryType {
+    /// # Safety
+    ///
+    /// `memory_type` must be a valid memory type returned by WAMR
+    pub(crate) unsafe fn from_raw(memory_type: wasm_memory_type_t) -> Self {
+        MemoryType {
+            init_pages: wasm_memory_type_get_init_page_count(memory_type),
+            max_pages: wasm_memory_type_get_max_page_count(memory_type),
+            shared: wasm_memory_type_get_shared(memory_type),
+        }
+    }
+}
+
+impl GlobalType {
+    /// # Safety
+    ///
+    /// `global_type` must be a valid global type returned by WAMR
+    pub(crate) unsafe fn from_raw(global_type: wasm_global_type_t) -> Self {
+        GlobalType {
+            val_type: WasmValType::from_valkind(wasm_global_type_get_valkind(global_type) as u32),
+            mutable: wasm_global_type_get_mutable(global_type),
+        }
+    }
+}
+
+impl ImportType {
+    /// # Safety
+    ///
+    /// `import` must be filled by `wasm_runtime_get_import_type()`
+    pub(crate) unsafe fn from_raw(import: &wasm_import_t) -> Self {
+        let ty = match import.kind {
+            wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_FUNC => {
+                ExternType::Func(FuncType::from_raw(import.u.func_type))
+            }
+            wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_TABLE => {
+                ExternType::Table(TableType::from_raw(import.u.table_type))
+            }
+            wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_MEMORY => {
+                ExternType::Memory(MemoryType::from_raw(import.u.memory_type))
+            }
+            wasm_import_export_kind_t_WASM_IMPORT_EXPORT_KIND_GLOBAL => {
+                ExternType::Global(GlobalType::from_raw(import.u.global_type))
+            }
+            kind => ExternType::Unsupported(kind),
+        };
+
+        ImportType {
+            module_name: cstr_to_string(import.module_name),
+            name: cstr_to_string(import.name),
+            ty,
+            linked: import.linked,
+        }
+    }
+}
@@ -5,6 +5,43 @@
 
 //! a wasm value. Always used as function parameters and results
 
+#![allow(non_upper_case_globals)]
+
+use wamr_sys::{
+    wasm_valkind_enum_WASM_EXTERNREF, wasm_valkind_enum_WASM_F32, wasm_valkind_enum_WASM_F64,
+    wasm_valkind_enum_WASM_FUNCREF, wasm_valkind_enum_WASM_I32, wasm_valkind_enum_WASM_I64,
+    wasm_valkind_enum_WASM_V128,
+};
+
+/// the type of a wasm value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    V128,
+    FuncRef,
+    ExternRef,
+    /// a value kind this SDK doesn't know about yet. like GC reference types
+    Unsupported(u32),
+}
+
+impl WasmValType {
+    pub(crate) fn from_valkind(kind: u32) -> WasmValType {
+        match kind {
+            wasm_valkind_enum_WASM_I32 => WasmValType::I32,
+            wasm_valkind_enum_WASM_I64 => WasmValType::I64,
+            wasm_valkind_enum_WASM_F32 => WasmValType::F32,
+            wasm_valkind_enum_WASM_F64 => WasmValType::F64,
+            wasm_valkind_enum_WASM_V128 => WasmValType::V128,
+            wasm_valkind_enum_WASM_FUNCREF => WasmValType::FuncRef,
+            wasm_valkind_enum_WASM_EXTERNREF => WasmValType::ExternRef,
+            _ => WasmValType::Unsupported(kind),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum WasmValue {
     Void,
@@ -73,6 +110,35 @@ impl WasmValue {
         let binary: [u32; 4] = [binary[0], binary[1], binary[2], binary[3]];
         WasmValue::V128(unsafe { std::mem::transmute::<[u32; 4], i128>(binary) })
     }
+
+    /// decode a value from a `uint64` slot of a raw host function.
+    /// i32 and f32 are kept in the lower 32 bits.
+    ///
+    /// return `None` if the type can't be passed in a single slot
+    pub fn decode_from_raw_slot(val_type: WasmValType, slot: u64) -> Option<WasmValue> {
+        match val_type {
+            WasmValType::I32 | WasmValType::FuncRef | WasmValType::ExternRef => {
+                Some(WasmValue::I32(slot as u32 as i32))
+            }
+            WasmValType::I64 => Some(WasmValue::I64(slot as i64)),
+            WasmValType::F32 => Some(WasmValue::F32(f32::from_bits(slot as u32))),
+            WasmValType::F64 => Some(WasmValue::F64(f64::from_bits(slot))),
+            _ => None,
+        }
+    }
+
+    /// encode a value into a `uint64` slot of a raw host function
+    ///
+    /// return `None` if the value can't be passed in a single slot
+    pub fn encode_to_raw_slot(&self) -> Option<u64> {
+        match *self {
+            WasmValue::I32(value) => Some(value as u32 as u64),
+            WasmValue::I64(value) => Some(value as u64),
+            WasmValue::F32(value) => Some(value.to_bits() as u64),
+            WasmValue::F64(value) => Some(value.to_bits()),
+            WasmValue::Void | WasmValue::V128(_) => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -117,4 +183,23 @@ mod tests {
 
         assert_eq!(values, decoded_values);
     }
+
+    #[test]
+    fn test_raw_slot() {
+        let values = vec![
+            (WasmValType::I32, WasmValue::I32(-1)),
+            (WasmValType::I64, WasmValue::I64(-2)),
+            (WasmValType::F32, WasmValue::F32(3.0)),
+            (WasmValType::F64, WasmValue::F64(4.0)),
+        ];
+
+        for (val_type, value) in values {
+            let slot = value.encode_to_raw_slot().unwrap();
+            assert_eq!(WasmValue::decode_from_raw_slot(val_type, slot), Some(value));
+        }
+
+        assert_eq!(WasmValue::I32(-1).encode_to_raw_slot(), Some(0xffff_ffff));
+        assert_eq!(WasmValue::V128(1).encode_to_raw_slot(), None);
+        assert_eq!(WasmValue::decode_from_raw_slot(WasmValType::V128, 0), None);
+    }
 }
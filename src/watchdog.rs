@@ -0,0 +1,95 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! a process-wide thread to terminate instances running beyond their deadlines
+
+use std::{
+    collections::BTreeMap,
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::Instant,
+};
+
+use crate::instance::TerminateHandle;
+
+#[derive(Default)]
+struct Deadlines {
+    next_id: u64,
+    // ordered by the deadline, the id tells apart equal deadlines
+    entries: BTreeMap<(Instant, u64), TerminateHandle>,
+}
+
+struct Watchdog {
+    deadlines: Mutex<Deadlines>,
+    changed: Condvar,
+}
+
+/// The deadline is cancelled when the guard is dropped
+pub struct DeadlineGuard {
+    key: (Instant, u64),
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        watchdog()
+            .deadlines
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&self.key);
+    }
+}
+
+fn watchdog() -> &'static Watchdog {
+    static WATCHDOG: OnceLock<Watchdog> = OnceLock::new();
+    WATCHDOG.get_or_init(|| {
+        thread::Builder::new()
+            .name(String::from("wamr-watchdog"))
+            .spawn(|| watchdog().run())
+            .expect("failed to spawn the watchdog thread");
+
+        Watchdog {
+            deadlines: Mutex::new(Deadlines::default()),
+            changed: Condvar::new(),
+        }
+    })
+}
+
+impl Watchdog {
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        loop {
+            let first = deadlines.entries.keys().next().copied();
+            deadlines = match first {
+                None => self.changed.wait(deadlines).unwrap(),
+                Some(key) => {
+                    let now = Instant::now();
+                    if key.0 <= now {
+                        if let Some(handle) = deadlines.entries.remove(&key) {
+                            handle.time_out();
+                        }
+                        deadlines
+                    } else {
+                        self.changed.wait_timeout(deadlines, key.0 - now).unwrap().0
+                    }
+                }
+            };
+        }
+    }
+}
+
+/// terminate the instance behind `handle` when `deadline` passes,
+/// unless the returned guard is dropped before that
+pub fn arm(deadline: Instant, handle: TerminateHandle) -> DeadlineGuard {
+    let watchdog = watchdog();
+
+    let mut deadlines = watchdog.deadlines.lock().unwrap();
+    let key = (deadline, deadlines.next_id);
+    deadlines.next_id += 1;
+    deadlines.entries.insert(key, handle);
+
+    watchdog.changed.notify_one();
+    DeadlineGuard { key }
+}
@@ -0,0 +1,143 @@
+/*
+ * Copyright (C) 2019 Intel Corporation. All rights reserved.
+ * SPDX-License-Identifier: Apache-2.0 WITH LLVM-exception
+ */
+
+//! the lifecycle of the process-wide runtime. these tests create and destroy it, so
+//! they run in their own process, one at a time, instead of among the unit tests,
+//! which keep the runtime alive meanwhile
+
+use std::sync::{Mutex, MutexGuard};
+
+use wamr_rust_sdk::{
+    runtime::Runtime,
+    sys::{wasm_runtime_free, wasm_runtime_malloc},
+    RuntimeError,
+};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+// a failed test doesn't stop the others
+fn serial() -> MutexGuard<'static, ()> {
+    SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[test]
+fn test_runtime_new() {
+    let _serial = serial();
+
+    let runtime = Runtime::new();
+    assert!(runtime.is_ok());
+
+    /* use malloc to confirm */
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(!small_buf.is_null());
+    unsafe { wasm_runtime_free(small_buf) };
+
+    drop(runtime);
+
+    /* runtime has been destroyed. malloc should be failed */
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(small_buf.is_null());
+
+    {
+        let runtime = Runtime::new();
+        assert!(runtime.is_ok());
+
+        let runtime = Runtime::new();
+        assert!(runtime.is_ok());
+
+        let runtime = Runtime::new();
+        assert!(runtime.is_ok());
+    }
+
+    /* runtime has been destroyed. malloc should be failed */
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(small_buf.is_null());
+}
+
+#[test]
+fn test_runtime_shared() {
+    let _serial = serial();
+
+    let runtime = Runtime::new().unwrap();
+    let other = runtime.clone();
+    drop(runtime);
+
+    /* the runtime is alive with other handles */
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(!small_buf.is_null());
+    unsafe { wasm_runtime_free(small_buf) };
+
+    let same = Runtime::builder().use_system_allocator().build();
+    assert!(same.is_ok());
+
+    let conflicting = Runtime::builder()
+        .use_system_allocator()
+        .import_resolver(|_, _, _| None)
+        .build();
+    assert!(matches!(
+        conflicting,
+        Err(RuntimeError::ConflictingConfiguration(_))
+    ));
+
+    drop(same);
+    drop(other);
+
+    /* the last handle is dropped */
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(small_buf.is_null());
+
+    /* both have resolvers, which can't be told apart */
+    let resolving = Runtime::builder()
+        .use_system_allocator()
+        .import_resolver(|_, _, _| None)
+        .build();
+    assert!(resolving.is_ok());
+
+    let conflicting = Runtime::builder()
+        .use_system_allocator()
+        .import_resolver(|_, _, _| None)
+        .build();
+    assert!(matches!(
+        conflicting,
+        Err(RuntimeError::ConflictingConfiguration(_))
+    ));
+
+    /* and a runtime without one doesn't match either */
+    let conflicting = Runtime::builder().use_system_allocator().build();
+    assert!(matches!(
+        conflicting,
+        Err(RuntimeError::ConflictingConfiguration(_))
+    ));
+}
+
+#[test]
+fn test_runtime_builder_default() {
+    let _serial = serial();
+
+    // use Mode_Default
+    let runtime = Runtime::builder().use_system_allocator().build();
+    assert!(runtime.is_ok());
+
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(!small_buf.is_null());
+    unsafe { wasm_runtime_free(small_buf) };
+}
+
+#[test]
+fn test_runtime_builder_interpreter() {
+    let _serial = serial();
+
+    let runtime = Runtime::builder()
+        .run_as_interpreter()
+        .use_system_allocator()
+        .build();
+    assert!(runtime.is_ok());
+
+    let small_buf = unsafe { wasm_runtime_malloc(16) };
+    assert!(!small_buf.is_null());
+    unsafe { wasm_runtime_free(small_buf) };
+}